@@ -0,0 +1,19 @@
+use crate::{Context, Error};
+
+#[poise::command(slash_command, prefix_command, category = "util")]
+pub async fn cachestats(ctx: Context<'_>) -> Result<(), Error> {
+    let stats = ctx.data().resolve_cache.stats();
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = if lookups == 0 {
+        0.0
+    } else {
+        stats.hits as f64 * 100.0 / lookups as f64
+    };
+
+    ctx.say(format!(
+        "Resolve cache: {} entries, {} hits and {} misses ({:.0}% of lookups skipped spotdl or yt-dlp)",
+        stats.entries, stats.hits, stats.misses, hit_rate
+    ))
+    .await?;
+    Ok(())
+}
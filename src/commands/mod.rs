@@ -1,11 +1,32 @@
+pub mod cache;
 pub mod help;
 pub mod ping;
 pub mod player;
 
+use cache::cachestats;
 use help::help;
 use ping::ping;
 use player::{
-    join::join, query::query, queue::queue, skip::skip, spotify::spotify, stop::stop, yt::yt,
+    attachment::{play, play_attachment},
+    clear::clear,
+    join::join,
+    local::local,
+    loop_mode::loop_mode,
+    move_track::move_track,
+    nowplaying::nowplaying,
+    pause::pause,
+    query::query,
+    queue::queue,
+    remove::remove,
+    resume::resume,
+    seek::seek,
+    shuffle::shuffle,
+    skip::skip,
+    spotify::spotify,
+    stop::stop,
+    swap::swap,
+    volume::volume,
+    yt::yt,
 };
 
 use crate::Error;
@@ -15,12 +36,27 @@ pub fn create_command() -> Vec<Command<crate::Data, Error>> {
     vec![
         help(),
         ping(),
+        cachestats(),
         join(),
+        nowplaying(),
         yt(),
         spotify(),
+        local(),
+        play(),
+        play_attachment(),
         query(),
         queue(),
         skip(),
+        pause(),
+        resume(),
+        seek(),
+        loop_mode(),
+        volume(),
         stop(),
+        remove(),
+        move_track(),
+        swap(),
+        clear(),
+        shuffle(),
     ]
 }
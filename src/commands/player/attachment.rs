@@ -0,0 +1,130 @@
+use crate::{
+    input::sources::attachment::AttachmentFile,
+    player::track::{self, TrackRequest},
+    Context, Error, HttpKey,
+};
+use poise::serenity_prelude::{Attachment, Message};
+
+use super::{autocomplete::autocomplete_song, join::handle_join, yt::handle_play_yt};
+
+#[poise::command(prefix_command, track_edits, slash_command)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "Audio file to play"] attachment: Option<Attachment>,
+    #[description = "Url to the song, or what to search for, when nothing is attached"]
+    #[autocomplete = "autocomplete_song"]
+    url: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    match (attachment, url) {
+        (Some(attachment), _) => handle_play_attachments(ctx, vec![attachment], 0, 2).await?,
+        (None, Some(url)) => handle_play_yt(ctx, url, 0, 2).await?,
+        (None, None) => {
+            ctx.reply("Attach an audio file or give a url to play")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(context_menu_command = "Play attachment")]
+pub async fn play_attachment(
+    ctx: Context<'_>,
+    #[description = "Message with the audio files to play"] message: Message,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    if message.attachments.is_empty() {
+        ctx.reply("The message has no attachments").await?;
+        return Ok(());
+    }
+    handle_play_attachments(ctx, message.attachments, 0, 2).await?;
+
+    Ok(())
+}
+
+async fn handle_play_attachments(
+    ctx: Context<'_>,
+    attachments: Vec<Attachment>,
+    trial_time: i8,
+    max_trial_time: i8,
+) -> Result<(), Error> {
+    if trial_time >= max_trial_time {
+        ctx.reply("Tried to join the channle multiple times but fail")
+            .await?;
+        return Ok(());
+    }
+
+    let ser_ctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().expect("have guild_id");
+
+    let http_client = {
+        let data = ser_ctx.data.read().await;
+        data.get::<HttpKey>()
+            .cloned()
+            .expect("Guaranteed to exist in the typemap.")
+    };
+
+    // refuse what isn't audio before joining anything
+    let max_bytes = ctx.data().app_config.max_attachment_bytes();
+    let (sources, refused): (Vec<_>, Vec<_>) = attachments
+        .iter()
+        .map(|attachment| AttachmentFile::new(http_client.clone(), attachment, max_bytes))
+        .partition(Result::is_ok);
+    let sources: Vec<_> = sources.into_iter().filter_map(Result::ok).collect();
+    let refused: Vec<_> = refused.into_iter().filter_map(Result::err).collect();
+
+    if sources.is_empty() {
+        ctx.reply(refused.join("\n")).await?;
+        return Ok(());
+    }
+
+    let manager = songbird::get(ser_ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    match manager.get(guild_id) {
+        Some(handler_lock) => {
+            let mut requests = vec![];
+            for src in sources {
+                requests.push(TrackRequest::new(ctx, src).resolve_metadata().await);
+            }
+
+            let mut handler = handler_lock.lock().await;
+            let queued = requests.len();
+            for request in requests {
+                track::enqueue(&mut handler, &ctx.data().player, request).await;
+            }
+
+            let q_len = handler.queue().len();
+            println!("current queue length {}", q_len);
+
+            let mut reply = if queued == 1 {
+                "Playing song".to_string()
+            } else {
+                format!("Queued {} attachments", queued)
+            };
+            for e in refused {
+                reply.push_str(&format!("\nSkipped {}", e));
+            }
+            ctx.reply(reply).await?;
+        }
+        _ => {
+            ctx.reply("Not in a voice channel to play in, joining...")
+                .await?;
+            if handle_join(ctx).await.is_ok() {
+                let future = Box::pin(handle_play_attachments(
+                    ctx,
+                    attachments,
+                    trial_time + 1,
+                    max_trial_time,
+                ));
+                future.await?;
+            }
+        }
+    }
+    Ok(())
+}
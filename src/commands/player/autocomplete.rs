@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    input::sources::ytdl::YoutubeDl,
+    player::embed::{truncate, CHOICE_TEXT_LIMIT},
+    Context, HttpKey,
+};
+use poise::serenity_prelude::{AutocompleteChoice, UserId};
+
+const SUGGESTION_COUNT: usize = 5;
+const MIN_QUERY_LEN: usize = 3;
+// wait for the user to stop typing before searching
+const DEBOUNCE: Duration = Duration::from_millis(400);
+// discord drops autocomplete responses after 3 seconds, slower searches still fill the cache
+const RESPONSE_DEADLINE: Duration = Duration::from_millis(2500);
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const CACHE_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+struct Suggestion {
+    title: String,
+    url: String,
+}
+
+struct CachedSearch {
+    fetched: Instant,
+    suggestions: Vec<Suggestion>,
+}
+
+/// Recent youtube search suggestions, shared by every autocompleted song parameter.
+#[derive(Default)]
+pub struct SongSuggestions {
+    cache: Mutex<HashMap<String, CachedSearch>>,
+    // latest keystroke of each user, older ones give up instead of searching
+    latest: Mutex<HashMap<UserId, u64>>,
+    keystrokes: AtomicU64,
+}
+
+impl SongSuggestions {
+    fn cached(&self, query: &str) -> Option<Vec<Suggestion>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(query)
+            .filter(|cached| cached.fetched.elapsed() < CACHE_TTL)
+            .map(|cached| cached.suggestions.clone())
+    }
+
+    fn insert(&self, query: String, suggestions: Vec<Suggestion>) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, cached| cached.fetched.elapsed() < CACHE_TTL);
+        if cache.len() >= CACHE_CAPACITY {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.fetched)
+                .map(|(query, _)| query.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+
+        cache.insert(
+            query,
+            CachedSearch {
+                fetched: Instant::now(),
+                suggestions,
+            },
+        );
+    }
+
+    fn keystroke(&self, user_id: UserId) -> u64 {
+        let keystroke = self.keystrokes.fetch_add(1, Ordering::Relaxed);
+        self.latest.lock().unwrap().insert(user_id, keystroke);
+        keystroke
+    }
+
+    fn is_latest(&self, user_id: UserId, keystroke: u64) -> bool {
+        self.latest.lock().unwrap().get(&user_id) == Some(&keystroke)
+    }
+}
+
+/// Suggests youtube videos matching what was typed so far, picking one fills in its url.
+pub async fn autocomplete_song(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let query = partial
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if query.chars().count() < MIN_QUERY_LEN || query.starts_with("http") {
+        return vec![];
+    }
+
+    let suggestions = ctx.data().song_suggestions.clone();
+    if let Some(cached) = suggestions.cached(&query) {
+        return into_choices(cached);
+    }
+
+    let keystroke = suggestions.keystroke(ctx.author().id);
+    tokio::time::sleep(DEBOUNCE).await;
+    if !suggestions.is_latest(ctx.author().id, keystroke) {
+        return vec![];
+    }
+
+    let http_client = {
+        let data = ctx.serenity_context().data.read().await;
+        data.get::<HttpKey>()
+            .cloned()
+            .expect("Guaranteed to exist in the typemap.")
+    };
+
+    let ytdlp = ctx.data().app_config.ytdlp();
+    let resolve_cache = ctx.data().resolve_cache.clone();
+    let search = tokio::spawn(async move {
+        let results = YoutubeDl::new_search(http_client, query.clone())
+            .with_tool(ytdlp)
+            .with_cache(resolve_cache)
+            .search(Some(SUGGESTION_COUNT))
+            .await
+            .map_err(|e| println!("Autocomplete search for {} failed: {:?}", query, e))
+            .ok()?;
+
+        let found: Vec<Suggestion> = results
+            .into_iter()
+            .filter_map(|result| {
+                Some(Suggestion {
+                    title: result.title?,
+                    url: result.source_url?,
+                })
+            })
+            .collect();
+        suggestions.insert(query, found.clone());
+        Some(found)
+    });
+
+    match tokio::time::timeout(RESPONSE_DEADLINE, search).await {
+        Ok(Ok(Some(found))) => into_choices(found),
+        _ => vec![],
+    }
+}
+
+fn into_choices(suggestions: Vec<Suggestion>) -> Vec<AutocompleteChoice> {
+    suggestions
+        .into_iter()
+        // longer urls can't be a choice value, playlists links and the like are not worth it
+        .filter(|suggestion| suggestion.url.len() <= CHOICE_TEXT_LIMIT)
+        .map(|suggestion| {
+            AutocompleteChoice::new(
+                truncate(&suggestion.title, CHOICE_TEXT_LIMIT),
+                suggestion.url,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,161 @@
+use crate::{
+    player::{
+        embed::{truncate, CHOICE_TEXT_LIMIT},
+        track,
+    },
+    Context, Error,
+};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+
+// cleared tracks listed in the reply, discord caps embed descriptions at 4096 characters
+const LISTED_TRACKS: usize = 10;
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn clear(
+    ctx: Context<'_>,
+    #[description = "Positions to clear, inclusive, e.g. 3..7, 3.. or ..7"] range: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let Some((start, end)) = parse_range(&range) else {
+        ctx.reply("Range must look like 3..7, 3.. or ..7").await?;
+        return Ok(());
+    };
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    let cleared = queue.modify_queue(|tracks| {
+        let (start, end) = clear_bounds(start, end, tracks.len())?;
+        Ok::<_, String>((start, tracks.drain(start..=end).collect::<Vec<_>>()))
+    });
+
+    match cleared {
+        Ok((start, cleared)) => {
+            let mut titles = Vec::with_capacity(cleared.len().min(LISTED_TRACKS));
+            for queued in &cleared {
+                // dequeued tracks are not stopped by songbird, they would linger in the driver
+                let _ = queued.stop();
+                if titles.len() < LISTED_TRACKS {
+                    titles.push(track::title(queued).await);
+                }
+            }
+
+            let embed = CreateEmbed::new()
+                .title(format!("Cleared {} tracks from the queue", cleared.len()))
+                .description(cleared_list(start, &titles, cleared.len()));
+            ctx.send(CreateReply::default().embed(embed)).await?;
+        }
+        Err(reason) => {
+            ctx.reply(reason).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// parses `start..end`, either bound may be left out
+fn parse_range(range: &str) -> Option<(Option<usize>, Option<usize>)> {
+    let (start, end) = range.trim().split_once("..")?;
+    let bound = |bound: &str| match bound.trim() {
+        "" => Ok(None),
+        bound => bound.parse().map(Some),
+    };
+
+    Some((bound(start).ok()?, bound(end).ok()?))
+}
+
+// fills in left out bounds of a range parsed by `parse_range` and checks it fits a queue of
+// `len` tracks, the playing one excluded
+fn clear_bounds(
+    start: Option<usize>,
+    end: Option<usize>,
+    len: usize,
+) -> Result<(usize, usize), String> {
+    let start = start.unwrap_or(1);
+    let end = end.unwrap_or(len.saturating_sub(1));
+    track::check_position(start, len)?;
+    track::check_position(end, len)?;
+    if start > end {
+        return Err(format!("Start {} is after end {}", start, end));
+    }
+
+    Ok((start, end))
+}
+
+// numbers the listed titles by their former position, mentioning how many more went. Titles
+// are cut down so that even the longest ones fit the embed
+fn cleared_list(start: usize, titles: &[String], cleared: usize) -> String {
+    let mut lines: Vec<String> = titles
+        .iter()
+        .enumerate()
+        .map(|(offset, title)| {
+            format!(
+                "`{}.` {}",
+                start + offset,
+                truncate(title, CHOICE_TEXT_LIMIT)
+            )
+        })
+        .collect();
+    if cleared > titles.len() {
+        lines.push(format!("…and {} more", cleared - titles.len()));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_parse() {
+        assert_eq!(parse_range("3..7"), Some((Some(3), Some(7))));
+        assert_eq!(parse_range(" 3 .. 7 "), Some((Some(3), Some(7))));
+        assert_eq!(parse_range("3.."), Some((Some(3), None)));
+        assert_eq!(parse_range("..7"), Some((None, Some(7))));
+        assert_eq!(parse_range(".."), Some((None, None)));
+        assert_eq!(parse_range("4..4"), Some((Some(4), Some(4))));
+
+        for malformed in ["3", "3-7", "a..7", "3..b", "-1..2", ""] {
+            assert_eq!(parse_range(malformed), None, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn ranges_fit_the_queue() {
+        // the playing track and 5 queued after it
+        let len = 6;
+        assert_eq!(clear_bounds(Some(2), Some(4), len), Ok((2, 4)));
+        assert_eq!(clear_bounds(Some(3), Some(3), len), Ok((3, 3)));
+        assert_eq!(clear_bounds(None, None, len), Ok((1, 5)));
+        assert_eq!(clear_bounds(Some(4), None, len), Ok((4, 5)));
+        assert_eq!(clear_bounds(None, Some(2), len), Ok((1, 2)));
+
+        let e = clear_bounds(Some(4), Some(2), len).unwrap_err();
+        assert!(e.contains("after"), "{e}");
+        for (start, end) in [(Some(0), Some(2)), (Some(2), Some(6)), (Some(9), None)] {
+            let e = clear_bounds(start, end, len).unwrap_err();
+            assert!(e.contains("between 1 and 5"), "{start:?}..{end:?}: {e}");
+        }
+        assert!(clear_bounds(None, None, 1).is_err());
+    }
+
+    #[test]
+    fn long_clears_are_cut_short() {
+        let titles: Vec<String> = (0..3).map(|i| format!("song {i}")).collect();
+        assert_eq!(
+            cleared_list(4, &titles, 3),
+            "`4.` song 0\n`5.` song 1\n`6.` song 2"
+        );
+
+        let titles: Vec<String> = (0..LISTED_TRACKS).map(|_| "a".repeat(1000)).collect();
+        let list = cleared_list(1, &titles, 500);
+        assert!(list.ends_with("\n…and 490 more"), "{list}");
+        assert!(list.chars().count() <= 4096);
+    }
+}
@@ -1,21 +1,62 @@
-use std::collections::HashMap;
-
-use crate::{Context, Error};
-use poise::serenity_prelude::{async_trait, UserId, VoiceState};
-use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler};
-
-struct TrackErrorNotifier;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    player::track::{self, TrackRequestKey},
+    Context, Error,
+};
+use poise::serenity_prelude::{async_trait, Http, UserId, VoiceState};
+use songbird::{
+    events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent},
+    tracks::{PlayError, PlayMode, TrackQueue},
+};
+
+/// Reports tracks that failed to play to the channel they were requested from.
+struct TrackErrorNotifier {
+    http: Arc<Http>,
+    queue: TrackQueue,
+}
 
 #[async_trait]
 impl VoiceEventHandler for TrackErrorNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(track_list) = ctx {
-            for (state, handle) in *track_list {
-                println!(
-                    "Track {:?} encountered an error: {:?}",
-                    handle.uuid(),
-                    state.playing
-                );
+        let EventContext::Track(track_list) = ctx else {
+            return None;
+        };
+
+        for (state, handle) in *track_list {
+            let PlayMode::Errored(error) = &state.playing else {
+                continue;
+            };
+
+            let reason = match error {
+                // the AudioStreamError from the source says more than the wrapper around it
+                PlayError::Create(e) => e.to_string(),
+                e => e.to_string(),
+            };
+            println!("Track {:?} encountered an error: {reason}", handle.uuid());
+
+            // the queue only advances by itself when its head errors, a track failing while
+            // being preloaded has to be taken out by hand
+            let uuid = handle.uuid();
+            self.queue.modify_queue(|queue| {
+                if queue.front().map(|t| t.uuid()) != Some(uuid) {
+                    queue.retain(|t| t.uuid() != uuid);
+                }
+            });
+
+            let channel_id = handle
+                .typemap()
+                .read()
+                .await
+                .get::<TrackRequestKey>()
+                .map(|request| request.channel_id);
+            let Some(channel_id) = channel_id else {
+                continue;
+            };
+
+            let message = format!("Failed to play {}: {reason}", track::title(handle).await);
+            if let Err(e) = channel_id.say(&self.http, message).await {
+                println!("Failed to report track error: {:?}", e);
             }
         }
 
@@ -40,7 +81,7 @@ pub async fn handle_join(ctx: Context<'_>) -> Result<(), Error> {
     let ser_ctx = ctx.serenity_context();
 
     let (guild_id, channel_id) = {
-        let guild = ctx.clone().guild().expect("have guild");
+        let guild = ctx.guild().expect("have guild");
 
         let voice_status: &HashMap<UserId, VoiceState> = &guild.voice_states;
         let voice_state = voice_status.get(&author_id);
@@ -58,11 +99,20 @@ pub async fn handle_join(ctx: Context<'_>) -> Result<(), Error> {
     };
 
     let manager = songbird::get(ser_ctx).await.expect("have manager");
+    // joining again, or moving channels, reuses the call along with its handlers
+    if manager.get(guild_id).is_none() {
+        // Attach an event handler to see notifications of all track errors.
+        let handler_lock = manager.get_or_insert(guild_id);
+        let mut handler = handler_lock.lock().await;
+        let notifier = TrackErrorNotifier {
+            http: ser_ctx.http.clone(),
+            queue: handler.queue().clone(),
+        };
+        handler.add_global_event(Event::Track(TrackEvent::Error), notifier);
+    }
 
     match manager.join(guild_id, connect_to).await {
         Ok(_) => {
-            // Attach an event handler to see notifications of all track errors.
-
             ctx.reply("Joined").await?;
         }
         Err(e) => {
@@ -0,0 +1,104 @@
+use crate::{
+    input::sources::local::LocalFile,
+    player::track::{self, TrackRequest},
+    Context, Error,
+};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+
+use super::join::handle_join;
+
+#[poise::command(prefix_command, track_edits, slash_command)]
+pub async fn local(
+    ctx: Context<'_>,
+    #[description = "File or directory in the music library, * and ? match any characters"]
+    path: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    handle_play_local(ctx, path, 0, 2).await?;
+
+    Ok(())
+}
+
+async fn handle_play_local(
+    ctx: Context<'_>,
+    path: String,
+    trial_time: i8,
+    max_trial_time: i8,
+) -> Result<(), Error> {
+    if trial_time >= max_trial_time {
+        ctx.reply("Tried to join the channle multiple times but fail")
+            .await?;
+        return Ok(());
+    }
+
+    let Some(library) = &ctx.data().library else {
+        ctx.reply("No music library is configured").await?;
+        return Ok(());
+    };
+    let files = match library.find(&path).await {
+        Ok(files) => files,
+        Err(e) => {
+            ctx.reply(e).await?;
+            return Ok(());
+        }
+    };
+
+    let ser_ctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().expect("have guild_id");
+
+    let manager = songbird::get(ser_ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    match manager.get(guild_id) {
+        Some(handler_lock) => {
+            let found = files.len();
+            let max_tracks = ctx.data().app_config.max_tracks_per_command;
+            let mut requests = vec![];
+            for file in files.into_iter().take(max_tracks) {
+                requests.push(
+                    TrackRequest::new(ctx, LocalFile::new(file))
+                        .resolve_metadata()
+                        .await,
+                );
+            }
+
+            let mut handler = handler_lock.lock().await;
+            let queued = requests.len();
+            for request in requests {
+                track::enqueue(&mut handler, &ctx.data().player, request).await;
+            }
+
+            let q_len = handler.queue().len();
+            println!("current queue length {}", q_len);
+
+            if found == 1 {
+                ctx.reply("Playing song").await?;
+                return Ok(());
+            }
+
+            let mut embed =
+                CreateEmbed::new().title(format!("Queued {} tracks from {}", queued, path));
+            if queued < found {
+                embed = embed.description(format!(
+                    "Only the first {} of {} tracks were queued",
+                    queued, found
+                ));
+            }
+            ctx.send(CreateReply::default().embed(embed).ephemeral(false))
+                .await?;
+        }
+        _ => {
+            ctx.reply("Not in a voice channel to play in, joining...")
+                .await?;
+            if handle_join(ctx).await.is_ok() {
+                let future = Box::pin(handle_play_local(ctx, path, trial_time + 1, max_trial_time));
+                future.await?;
+            }
+        }
+    }
+    Ok(())
+}
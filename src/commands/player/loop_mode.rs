@@ -0,0 +1,37 @@
+use crate::{models::guild::LoopMode, player::track, Context, Error};
+
+#[poise::command(prefix_command, slash_command, rename = "loop")]
+pub async fn loop_mode(
+    ctx: Context<'_>,
+    #[description = "Repeat nothing, the current track or the whole queue"] mode: LoopMode,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    ctx.data()
+        .player
+        .guild_settings
+        .update(guild_id, |settings| settings.loop_mode = mode)
+        .await;
+
+    // tracks starting from now on pick the mode up by themselves, only the current one needs
+    // to be told
+    if let Some(current) = track::guild_queue(ctx.serenity_context(), guild_id)
+        .await
+        .and_then(|queue| queue.current())
+    {
+        match mode {
+            LoopMode::Track => current.enable_loop()?,
+            LoopMode::Off | LoopMode::Queue => current.disable_loop()?,
+        }
+    }
+
+    let reply = match mode {
+        LoopMode::Off => "Looping is off",
+        LoopMode::Track => "Looping the current track",
+        LoopMode::Queue => "Looping the queue",
+    };
+    ctx.reply(reply).await?;
+
+    Ok(())
+}
@@ -1,8 +1,21 @@
+pub mod attachment;
+pub mod autocomplete;
+pub mod clear;
 pub mod join;
+pub mod local;
+pub mod loop_mode;
+pub mod move_track;
+pub mod nowplaying;
+pub mod pause;
 pub mod query;
 pub mod queue;
+pub mod remove;
+pub mod resume;
+pub mod seek;
+pub mod shuffle;
 pub mod skip;
 pub mod spotify;
 pub mod stop;
+pub mod swap;
+pub mod volume;
 pub mod yt;
-
@@ -0,0 +1,51 @@
+use crate::{player::track, Context, Error};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+
+#[poise::command(prefix_command, slash_command, rename = "move")]
+pub async fn move_track(
+    ctx: Context<'_>,
+    #[description = "Position of the track to move"]
+    #[min = 1]
+    from: usize,
+    #[description = "Position to move the track to"]
+    #[min = 1]
+    to: usize,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    let moved = queue.modify_queue(|tracks| {
+        track::check_position(from, tracks.len())?;
+        track::check_position(to, tracks.len())?;
+
+        let moved = tracks.remove(from).expect("position was checked");
+        let handle = moved.handle();
+        tracks.insert(to, moved);
+        Ok::<_, String>(handle)
+    });
+
+    match moved {
+        Ok(moved) => {
+            let embed = CreateEmbed::new()
+                .title("Moved in the queue")
+                .description(format!(
+                    "{}: `{}.` → `{}.`",
+                    track::title(&moved).await,
+                    from,
+                    to
+                ));
+            ctx.send(CreateReply::default().embed(embed)).await?;
+        }
+        Err(reason) => {
+            ctx.reply(reason).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::{
+    player::{
+        embed::{now_playing_embed, progress_bar},
+        track::TrackRequestKey,
+    },
+    Context, Error,
+};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+use songbird::tracks::TrackHandle;
+
+const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+// interaction tokens expire after 15 minutes, the reply can't be edited past that
+const PROGRESS_UPDATE_WINDOW: Duration = Duration::from_secs(14 * 60);
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn nowplaying(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let ser_ctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().expect("have guild_id");
+
+    let manager = songbird::get(ser_ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let current = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current(),
+        None => None,
+    };
+
+    let Some(current) = current else {
+        ctx.reply("Nothing is playing right now").await?;
+        return Ok(());
+    };
+
+    let Some(embed) = progress_embed(&current).await else {
+        ctx.reply("Nothing is playing right now").await?;
+        return Ok(());
+    };
+    let reply = ctx.send(CreateReply::default().embed(embed)).await?;
+
+    // keep the progress bar moving for as long as the track plays
+    let started = tokio::time::Instant::now();
+    while started.elapsed() < PROGRESS_UPDATE_WINDOW {
+        tokio::time::sleep(PROGRESS_UPDATE_INTERVAL).await;
+
+        let Some(embed) = progress_embed(&current).await else {
+            break;
+        };
+        reply.edit(ctx, CreateReply::default().embed(embed)).await?;
+    }
+
+    Ok(())
+}
+
+// builds the embed for the current state of `track`, `None` once it stopped playing
+async fn progress_embed(track: &TrackHandle) -> Option<CreateEmbed> {
+    let state = track.get_info().await.ok()?;
+    if state.playing.is_done() {
+        return None;
+    }
+
+    let metadata = track
+        .typemap()
+        .read()
+        .await
+        .get::<TrackRequestKey>()
+        .map(|request| request.metadata.clone())
+        .unwrap_or_default();
+
+    Some(now_playing_embed(&metadata).field(
+        "Progress",
+        progress_bar(state.position, metadata.duration),
+        false,
+    ))
+}
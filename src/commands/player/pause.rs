@@ -0,0 +1,25 @@
+use crate::{player::track, Context, Error};
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    match queue.current() {
+        Some(current) => {
+            current.pause()?;
+            ctx.reply(format!("Paused {}", track::title(&current).await))
+                .await?;
+        }
+        None => {
+            ctx.reply("Nothing is playing right now").await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,21 @@
-use crate::{Context, Error, HttpKey};
-use poise::serenity_prelude::Message;
-use serde::{Deserialize, Serialize};
-use songbird::input::YoutubeDl;
+use std::time::Duration;
+
+use crate::{
+    input::sources::ytdl::YoutubeDl,
+    player::embed::{format_duration, truncate, CHOICE_TEXT_LIMIT},
+    Context, Error, HttpKey,
+};
+use poise::serenity_prelude::{
+    ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, Message,
+};
+use poise::CreateReply;
+
+use super::yt::handle_play_yt;
+
+const SEARCH_RESULTS: usize = 5;
+const PICK_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[poise::command(
     context_menu_command = "Query song",
@@ -11,68 +25,129 @@ use songbird::input::YoutubeDl;
 )]
 pub async fn query(
     ctx: Context<'_>,
-    #[description = "Url to the song"] url: Message,
+    #[description = "Message with the song to search for"] message: Message,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
-    // handle_query_song(ctx, url.content, 0, 2).await?;
+    handle_play_yt(ctx, message.content, 0, 2).await?;
 
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct QueryResult {
-    name: String,
-    value: String,
-}
-
-async fn handle_query_song(
-    ctx: Context<'_>,
-    url: String,
-    trial_time: i8,
-    max_trial_time: i8,
-) -> Result<(), Error> {
-    if trial_time >= max_trial_time {
-        ctx.reply("Tried to join the channle multiple times but fail")
-            .await?;
-        return Ok(());
-    }
-
-    let do_search = !url.starts_with("http");
-    let ser_ctx = ctx.serenity_context();
-    // let guild_id = ctx.guild_id().expect("have guild_id");
-
+/// Searches youtube for `query` and lets the invoking user pick one of the top results,
+/// returning the url of the pick, or `None` when nothing was found or picked in time.
+pub async fn handle_query_song(ctx: Context<'_>, query: String) -> Result<Option<String>, Error> {
     let http_client = {
-        let data = ser_ctx.data.read().await;
+        let data = ctx.serenity_context().data.read().await;
         data.get::<HttpKey>()
             .cloned()
             .expect("Guaranteed to exist in the typemap.")
     };
 
-    if do_search {
-        println!("searching...");
-        let search_res = YoutubeDl::new_search(http_client.clone(), url.clone())
-            .search(Some(5))
-            .await?;
+    println!("searching...");
+    let results: Vec<_> = YoutubeDl::new_search(http_client, query.clone())
+        .with_tool(ctx.data().app_config.ytdlp())
+        .with_cache(ctx.data().resolve_cache.clone())
+        .search(Some(SEARCH_RESULTS))
+        .await?
+        .into_iter()
+        .filter(|result| result.source_url.is_some())
+        .collect();
+    println!("search res length {}", results.len());
 
-        println!("search res length {}", search_res.len());
+    if results.is_empty() {
+        ctx.reply(format!("Nothing found for {}", query)).await?;
+        return Ok(None);
+    }
 
-        let mut command_res: Vec<QueryResult> = Vec::new();
+    let options = results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let title = result.title.as_deref().unwrap_or("Unknown");
+            let channel = result
+                .channel
+                .as_deref()
+                .or(result.artist.as_deref())
+                .unwrap_or("Unknown");
+            let duration = result
+                .duration
+                .map(format_duration)
+                .unwrap_or("?:??".to_string());
 
-        for res in search_res {
-            match (res.title, res.source_url) {
-                (Some(title), Some(url)) => command_res.push(QueryResult {
-                    name: title,
-                    value: url,
-                }),
-                _ => {}
-            }
-        }
+            CreateSelectMenuOption::new(truncate(title, CHOICE_TEXT_LIMIT), i.to_string())
+                .description(truncate(
+                    &format!("{} · {}", channel, duration),
+                    CHOICE_TEXT_LIMIT,
+                ))
+        })
+        .collect();
 
-        if let Ok(val) = serde_json::to_string(&command_res) {
-            ctx.reply(val).await?;
-        }
-    }
+    let menu_id = format!("{}pick", ctx.id());
+    let menu = CreateSelectMenu::new(&menu_id, CreateSelectMenuKind::String { options })
+        .placeholder("Pick a song to queue");
 
-    Ok(())
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(format!("Results for {}", query))
+                .components(vec![CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let press = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![menu_id])
+        .timeout(PICK_TIMEOUT)
+        .await;
+
+    let Some(press) = press else {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content("No song picked in time")
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(None);
+    };
+
+    let picked = match &press.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values
+            .first()
+            .and_then(|value| value.parse::<usize>().ok())
+            .and_then(|i| results.get(i)),
+        _ => None,
+    };
+    let Some(picked) = picked else {
+        // still answer the interaction, or discord shows it as failed
+        press
+            .create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .content("That song is no longer available, search again")
+                        .components(vec![]),
+                ),
+            )
+            .await?;
+        return Ok(None);
+    };
+
+    press
+        .create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(format!(
+                        "Picked {}",
+                        picked.title.as_deref().unwrap_or("Unknown")
+                    ))
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    Ok(picked.source_url.clone())
 }
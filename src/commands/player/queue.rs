@@ -1,13 +1,41 @@
-use crate::{Context, Error, HttpKey};
-use songbird::input::YoutubeDl;
+use std::time::Duration;
+
+use crate::{
+    input::sources::ytdl::YoutubeDl,
+    player::{
+        embed::format_duration,
+        track::{self, TrackRequest, TrackRequestKey},
+    },
+    Context, Error, HttpKey,
+};
+use poise::serenity_prelude::{
+    ComponentInteractionCollector, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponse, CreateInteractionResponseMessage, Mentionable,
+};
+use poise::CreateReply;
+
+use super::autocomplete::autocomplete_song;
+
+const TRACKS_PER_PAGE: usize = 10;
+// how long the page buttons keep working after the last press
+const PAGE_BUTTON_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 #[poise::command(prefix_command, track_edits, slash_command)]
 pub async fn queue(
     ctx: Context<'_>,
-    #[description = "Url to the song"] url: String,
+    #[description = "Url to the song, leave empty to show the queue"]
+    #[autocomplete = "autocomplete_song"]
+    url: Option<String>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
+    match url {
+        Some(url) => handle_queue_song(ctx, url).await,
+        None => handle_show_queue(ctx).await,
+    }
+}
+
+async fn handle_queue_song(ctx: Context<'_>, url: String) -> Result<(), Error> {
     if !url.starts_with("http") {
         ctx.reply("Must provide a valid URL").await?;
         return Ok(());
@@ -31,14 +59,18 @@ pub async fn queue(
 
     match manager.get(guild_id) {
         Some(handler_lock) => {
-            let mut handler = handler_lock.lock().await;
+            let src = YoutubeDl::new(http_client, url)
+                .with_tool(ctx.data().app_config.ytdlp())
+                .with_cache(ctx.data().resolve_cache.clone())
+                .with_audio_cache(ctx.data().audio_cache.clone());
+            let request = TrackRequest::new(ctx, src).resolve_metadata().await;
 
-            let src = YoutubeDl::new(http_client, url);
+            let mut handler = handler_lock.lock().await;
 
             let q_len = handler.queue().len();
             println!("current queue length {}", q_len);
 
-            handler.enqueue_input(src.into()).await;
+            track::enqueue(&mut handler, &ctx.data().player, request).await;
 
             ctx.reply("Queued song").await?;
         }
@@ -49,3 +81,138 @@ pub async fn queue(
 
     Ok(())
 }
+
+async fn handle_show_queue(ctx: Context<'_>) -> Result<(), Error> {
+    let ser_ctx = ctx.serenity_context();
+    let guild_id = ctx.guild_id().expect("have guild_id");
+
+    let manager = songbird::get(ser_ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let tracks = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current_queue(),
+        None => vec![],
+    };
+
+    if tracks.is_empty() {
+        ctx.reply("The queue is empty").await?;
+        return Ok(());
+    }
+
+    let mut lines = Vec::with_capacity(tracks.len());
+    let mut remaining = Duration::ZERO;
+    for (position, track) in tracks.iter().enumerate() {
+        let request = track
+            .typemap()
+            .read()
+            .await
+            .get::<TrackRequestKey>()
+            .cloned();
+        let title = request
+            .as_ref()
+            .and_then(|request| request.metadata.title.clone())
+            .unwrap_or("Unknown".to_string());
+        let duration = request
+            .as_ref()
+            .and_then(|request| request.metadata.duration);
+        let requester = request
+            .map(|request| request.requester.mention().to_string())
+            .unwrap_or("unknown".to_string());
+
+        // the head of the queue is the one playing, only what is left of it counts
+        let played = match position {
+            0 => track
+                .get_info()
+                .await
+                .map(|state| state.position)
+                .unwrap_or_default(),
+            _ => Duration::ZERO,
+        };
+        if let Some(duration) = duration {
+            remaining += duration.saturating_sub(played);
+        }
+
+        let duration = duration.map(format_duration).unwrap_or("?:??".to_string());
+        lines.push(match position {
+            0 => format!("**Now playing:** {} `{}` · {}", title, duration, requester),
+            _ => format!("`{}.` {} `{}` · {}", position, title, duration, requester),
+        });
+    }
+
+    let pages: Vec<String> = lines
+        .chunks(TRACKS_PER_PAGE)
+        .map(|page| page.join("\n"))
+        .collect();
+    let queue_embed = |page: usize| {
+        CreateEmbed::new()
+            .title("Queue")
+            .description(&pages[page])
+            .footer(CreateEmbedFooter::new(format!(
+                "Page {}/{} · {} upcoming · {} remaining",
+                page + 1,
+                pages.len(),
+                tracks.len() - 1,
+                format_duration(remaining)
+            )))
+    };
+
+    if pages.len() == 1 {
+        ctx.send(CreateReply::default().embed(queue_embed(0)))
+            .await?;
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{}prev", ctx_id);
+    let next_button_id = format!("{}next", ctx_id);
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&prev_button_id).emoji('◀'),
+        CreateButton::new(&next_button_id).emoji('▶'),
+    ]);
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(queue_embed(0))
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    let mut current_page = 0;
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGE_BUTTON_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1) % pages.len();
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.checked_sub(1).unwrap_or(pages.len() - 1);
+        } else {
+            continue;
+        }
+
+        press
+            .create_response(
+                ser_ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().embed(queue_embed(current_page)),
+                ),
+            )
+            .await?;
+    }
+
+    // the buttons stopped working, don't leave them around
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(queue_embed(current_page))
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::{player::track, Context, Error};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Position in the queue"]
+    #[min = 1]
+    position: usize,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    let removed = queue.modify_queue(|tracks| {
+        track::check_position(position, tracks.len())?;
+        Ok::<_, String>(tracks.remove(position).expect("position was checked"))
+    });
+
+    match removed {
+        Ok(removed) => {
+            // dequeued tracks are not stopped by songbird, they would linger in the driver
+            let _ = removed.stop();
+
+            let embed = CreateEmbed::new()
+                .title("Removed from the queue")
+                .description(format!("`{}.` {}", position, track::title(&removed).await));
+            ctx.send(CreateReply::default().embed(embed)).await?;
+        }
+        Err(reason) => {
+            ctx.reply(reason).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use crate::{
+    player::{embed::format_duration, track},
+    Context, Error,
+};
+use songbird::tracks::{ControlError, PlayError};
+
+#[derive(Debug, PartialEq)]
+enum SeekTarget {
+    To(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn seek(
+    ctx: Context<'_>,
+    #[description = "Where to seek to, e.g. 1:23, 83s, +10s or -30s"] timestamp: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let Some(target) = parse_timestamp(&timestamp) else {
+        ctx.reply("Timestamp must look like 1:23, 83s, +10s or -30s")
+            .await?;
+        return Ok(());
+    };
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(current) = track::guild_queue(ctx.serenity_context(), guild_id)
+        .await
+        .and_then(|queue| queue.current())
+    else {
+        ctx.reply("Nothing is playing right now").await?;
+        return Ok(());
+    };
+
+    let position = match target {
+        SeekTarget::To(position) => position,
+        SeekTarget::Forward(offset) => current.get_info().await?.position + offset,
+        SeekTarget::Backward(offset) => current.get_info().await?.position.saturating_sub(offset),
+    };
+
+    match current.seek_async(position).await {
+        Ok(position) => {
+            ctx.reply(format!("Seeked to {}", format_duration(position)))
+                .await?;
+        }
+        Err(ControlError::Play(PlayError::Seek(e))) => {
+            println!("Seek error {e:?}");
+            ctx.reply("This source does not support seeking, it can only be played from the start")
+                .await?;
+        }
+        Err(ControlError::Finished) => {
+            ctx.reply("The track already finished").await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
+
+// accepts `1:23`, `1:02:03`, `83`, `83s`, `1m30s`, each optionally prefixed by `+` or `-`
+// to seek relative to the current position
+fn parse_timestamp(timestamp: &str) -> Option<SeekTarget> {
+    let timestamp = timestamp.trim();
+    let (target, rest): (fn(Duration) -> SeekTarget, &str) = match timestamp.strip_prefix('+') {
+        Some(rest) => (SeekTarget::Forward, rest),
+        None => match timestamp.strip_prefix('-') {
+            Some(rest) => (SeekTarget::Backward, rest),
+            None => (SeekTarget::To, timestamp),
+        },
+    };
+
+    let secs = if rest.contains(':') {
+        parse_clock(rest)?
+    } else {
+        parse_units(rest)?
+    };
+
+    Some(target(Duration::from_secs(secs)))
+}
+
+// `1:23` or `1:02:03`
+fn parse_clock(clock: &str) -> Option<u64> {
+    let parts = clock
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if parts.len() > 3 || parts[1..].iter().any(|part| *part >= 60) {
+        return None;
+    }
+
+    Some(parts.iter().fold(0, |secs, part| secs * 60 + part))
+}
+
+// `83`, `83s`, `2m` or `1h2m3s`
+fn parse_units(units: &str) -> Option<u64> {
+    if units.is_empty() {
+        return None;
+    }
+
+    let mut secs = 0;
+    let mut number = String::new();
+    for c in units.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let scale = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        secs += number.parse::<u64>().ok()? * scale;
+        number.clear();
+    }
+    if !number.is_empty() {
+        secs += number.parse::<u64>().ok()?;
+    }
+
+    Some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks_parse() {
+        assert_eq!(parse_clock("1:23"), Some(83));
+        assert_eq!(parse_clock("0:05"), Some(5));
+        assert_eq!(parse_clock("75:00"), Some(4500));
+        assert_eq!(parse_clock("1:02:03"), Some(3723));
+
+        for malformed in [
+            "1:60", "1:02:60", "1:2:3:4", "1:", ":30", "1::2", "a:30", "1:-2",
+        ] {
+            assert_eq!(parse_clock(malformed), None, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn units_parse() {
+        assert_eq!(parse_units("83"), Some(83));
+        assert_eq!(parse_units("83s"), Some(83));
+        assert_eq!(parse_units("2m"), Some(120));
+        assert_eq!(parse_units("1m30s"), Some(90));
+        assert_eq!(parse_units("1m30"), Some(90));
+        assert_eq!(parse_units("1h2m3s"), Some(3723));
+
+        for malformed in ["", "s", "1x", "1.5s", "1 m", "m30s"] {
+            assert_eq!(parse_units(malformed), None, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn timestamps_parse() {
+        let secs = Duration::from_secs;
+        assert_eq!(parse_timestamp("1:23"), Some(SeekTarget::To(secs(83))));
+        assert_eq!(parse_timestamp(" 90s "), Some(SeekTarget::To(secs(90))));
+        assert_eq!(parse_timestamp("+10s"), Some(SeekTarget::Forward(secs(10))));
+        assert_eq!(
+            parse_timestamp("+1:00"),
+            Some(SeekTarget::Forward(secs(60)))
+        );
+        assert_eq!(parse_timestamp("-30"), Some(SeekTarget::Backward(secs(30))));
+        assert_eq!(parse_timestamp("-1m"), Some(SeekTarget::Backward(secs(60))));
+
+        for malformed in ["", "+", "-", "+-10", "--10", "1:2:3:4", "ten"] {
+            assert_eq!(parse_timestamp(malformed), None, "{malformed}");
+        }
+    }
+}
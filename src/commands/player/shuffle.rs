@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::{
+    player::track::{self, TrackRequestKey},
+    Context, Error,
+};
+use poise::serenity_prelude::UserId;
+use rand::seq::SliceRandom;
+use songbird::tracks::{Queued, TrackHandle};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Default, poise::ChoiceParameter)]
+pub enum ShuffleMode {
+    #[default]
+    #[name = "random"]
+    Random,
+    // avoids back to back tracks of the same artist or requester
+    #[name = "smart"]
+    Smart,
+}
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn shuffle(
+    ctx: Context<'_>,
+    #[description = "Plain random, or keep the same artist or requester from playing back to back"]
+    mode: Option<ShuffleMode>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    // the typemaps can't be read while the queue is locked, collect what smart mode needs first
+    let mode = mode.unwrap_or_default();
+    let keys = match mode {
+        ShuffleMode::Random => HashMap::new(),
+        ShuffleMode::Smart => shuffle_keys(&queue.current_queue()).await,
+    };
+
+    let shuffled = queue.modify_queue(|tracks| {
+        if tracks.len() <= 2 {
+            return 0;
+        }
+
+        // the head of the queue is playing, only what comes after it gets shuffled
+        let mut upcoming: Vec<Queued> = tracks.drain(1..).collect();
+        upcoming.shuffle(&mut rand::thread_rng());
+        if let ShuffleMode::Smart = mode {
+            let current = tracks.front().and_then(|current| keys.get(&current.uuid()));
+            upcoming = spread_out(upcoming, |queued| keys.get(&queued.uuid()), current);
+        }
+
+        let shuffled = upcoming.len();
+        tracks.extend(upcoming);
+        shuffled
+    });
+
+    if shuffled == 0 {
+        ctx.reply("Not enough tracks queued to shuffle").await?;
+    } else {
+        ctx.reply(format!("Shuffled {} tracks", shuffled)).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct ShuffleKey {
+    artist: Option<String>,
+    requester: Option<UserId>,
+}
+
+impl ShuffleKey {
+    fn clashes(&self, other: &ShuffleKey) -> usize {
+        let same_artist = self.artist.is_some() && self.artist == other.artist;
+        let same_requester = self.requester.is_some() && self.requester == other.requester;
+
+        usize::from(same_artist) + usize::from(same_requester)
+    }
+}
+
+async fn shuffle_keys(tracks: &[TrackHandle]) -> HashMap<Uuid, ShuffleKey> {
+    let mut keys = HashMap::with_capacity(tracks.len());
+    for track in tracks {
+        let key = match track.typemap().read().await.get::<TrackRequestKey>() {
+            Some(request) => ShuffleKey {
+                artist: request.metadata.artist.clone(),
+                requester: Some(request.requester),
+            },
+            None => ShuffleKey::default(),
+        };
+        keys.insert(track.uuid(), key);
+    }
+
+    keys
+}
+
+// greedily reorders the shuffled tracks so that each one shares as little as possible with
+// the one before it, keeping the shuffled order among equally good picks
+fn spread_out<'a, T>(
+    mut pending: Vec<T>,
+    key: impl Fn(&T) -> Option<&'a ShuffleKey>,
+    mut previous: Option<&'a ShuffleKey>,
+) -> Vec<T> {
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let mut best = 0;
+        let mut best_clashes = usize::MAX;
+        for (i, queued) in pending.iter().enumerate() {
+            let clashes = match (previous, key(queued)) {
+                (Some(previous), Some(key)) => previous.clashes(key),
+                _ => 0,
+            };
+            if clashes < best_clashes {
+                best = i;
+                best_clashes = clashes;
+            }
+            if clashes == 0 {
+                break;
+            }
+        }
+
+        let next = pending.remove(best);
+        previous = key(&next);
+        ordered.push(next);
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(artist: &str, requester: u64) -> ShuffleKey {
+        ShuffleKey {
+            artist: Some(artist.to_string()),
+            requester: Some(UserId::new(requester)),
+        }
+    }
+
+    // spreads out the indices of `keys`, in their given order
+    fn spread(keys: &[ShuffleKey], previous: Option<&ShuffleKey>) -> Vec<usize> {
+        spread_out((0..keys.len()).collect(), |&i| keys.get(i), previous)
+    }
+
+    #[test]
+    fn same_artists_are_spread_out() {
+        let keys = [
+            key("a", 1),
+            key("a", 2),
+            key("a", 3),
+            key("b", 4),
+            key("b", 5),
+            key("c", 6),
+        ];
+        let order = spread(&keys, None);
+        assert_eq!(order, [0, 3, 1, 4, 2, 5]);
+        for pair in order.windows(2) {
+            assert_eq!(keys[pair[0]].clashes(&keys[pair[1]]), 0, "{order:?}");
+        }
+
+        // nor does the playing track's artist play again right after it
+        assert_eq!(spread(&keys, Some(&key("a", 9)))[0], 3);
+    }
+
+    #[test]
+    fn fewest_clashes_win_and_ties_keep_their_order() {
+        // sharing both the artist and the requester is worse than sharing one of them
+        let keys = [key("a", 1), key("a", 1), key("a", 2), key("b", 1)];
+        assert_eq!(spread(&keys, Some(&key("a", 1))), [2, 3, 0, 1]);
+
+        // nothing to spread out, the shuffled order stays
+        let keys = [key("a", 1), key("a", 1), key("a", 1)];
+        assert_eq!(spread(&keys, None), [0, 1, 2]);
+
+        let unknown = [ShuffleKey::default(), ShuffleKey::default()];
+        assert_eq!(spread(&unknown, Some(&ShuffleKey::default())), [0, 1]);
+    }
+}
@@ -45,7 +45,7 @@ async fn handle_skip_current_song(
         _ => {
             ctx.reply("Not in a voice channel to play in, joining...")
                 .await?;
-            if let Ok(_) = handle_join(ctx).await {
+            if handle_join(ctx).await.is_ok() {
                 let future = Box::pin(handle_skip_current_song(
                     ctx,
                     trial_time + 1,
@@ -1,17 +1,21 @@
 use crate::{
-    input::sources::spotdl::{SpotifyCredential, SpotifyDl},
+    input::sources::spotdl::{ResolvePath, SpotifyCredential, SpotifyDl},
+    models::metadata::spotdl::Song,
+    player::track::{self, TrackRequest},
     Context, Error, HttpKey,
 };
-use poise::serenity_prelude::CreateEmbed;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter};
 use poise::CreateReply;
-use songbird::input::Compose;
+use reqwest::Client;
+use songbird::{input::Compose, Call};
+use tokio::sync::Mutex;
 
 use super::join::handle_join;
 
 #[poise::command(prefix_command, track_edits, slash_command)]
 pub async fn spotify(
     ctx: Context<'_>,
-    #[description = "Url to the song"] url: String,
+    #[description = "Url to the song, playlist or album"] url: String,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
@@ -48,16 +52,34 @@ async fn handle_play_spotify(
 
     match manager.get(guild_id) {
         Some(handler_lock) => {
-            let mut handler = handler_lock.lock().await;
+            let credentials = Some(SpotifyCredential {
+                client_id: ctx.data().app_config.spotify_client_id.clone(),
+                client_secret: ctx.data().app_config.spotify_client_secret.clone(),
+            });
+            let mut src = SpotifyDl::new(http_client.clone(), url.clone(), credentials.clone())
+                .with_api(ctx.data().spotify.clone())
+                .with_tools(
+                    ctx.data().app_config.spotdl(),
+                    ctx.data().app_config.ytdlp(),
+                )
+                .with_cache(ctx.data().resolve_cache.clone())
+                .with_audio_cache(ctx.data().audio_cache.clone());
 
-            let mut src = SpotifyDl::new(
-                http_client,
-                url.clone(),
-                Some(SpotifyCredential {
-                    client_id: ctx.data().app_config.spotify_client_id.clone(),
-                    client_secret: ctx.data().app_config.spotify_client_secret.clone(),
-                }),
-            );
+            // a failed lookup leaves `src` searching youtube instead, played as a single track
+            let songs = src.songs().await.unwrap_or_default();
+            if songs.len() > 1 || songs.first().is_some_and(|song| song.list_name.is_some()) {
+                let reply =
+                    enqueue_song_list(ctx, &handler_lock, http_client, credentials, songs, url)
+                        .await;
+                ctx.send(reply).await?;
+                return Ok(());
+            }
+
+            // the stream is looked up now rather than once the track plays, so the reply can
+            // tell whether spotdl found it
+            if let Err(e) = src.resolve().await {
+                println!("Resolving {} failed: {}", url, e);
+            }
 
             let meta = src.aux_metadata().await?;
             let embed = CreateEmbed::new()
@@ -66,11 +88,19 @@ async fn handle_play_spotify(
                 .description(meta.artist.unwrap_or("Unknown".to_string()))
                 .description(meta.album.unwrap_or("Unknown".to_string()))
                 .url(url)
-                .image(meta.thumbnail.unwrap_or("".to_string()));
+                .image(meta.thumbnail.unwrap_or("".to_string()))
+                .footer(CreateEmbedFooter::new(match src.resolve_path() {
+                    ResolvePath::Spotdl => "Found with spotdl".to_string(),
+                    ResolvePath::YoutubeSearch(query) => {
+                        format!("spotdl failed, playing the YouTube match for \"{}\"", query)
+                    }
+                }));
 
             let reply = CreateReply::default().embed(embed).ephemeral(false);
+            let request = TrackRequest::new(ctx, src).resolve_metadata().await;
 
-            handler.enqueue_input(src.into()).await;
+            let mut handler = handler_lock.lock().await;
+            track::enqueue(&mut handler, &ctx.data().player, request).await;
 
             let q_len = handler.queue().len();
             println!("current queue length {}", q_len);
@@ -81,7 +111,7 @@ async fn handle_play_spotify(
         _ => {
             ctx.reply("Not in a voice channel to play in, joining...")
                 .await?;
-            if let Ok(_) = handle_join(ctx).await {
+            if handle_join(ctx).await.is_ok() {
                 let future = Box::pin(handle_play_spotify(
                     ctx,
                     url,
@@ -94,3 +124,58 @@ async fn handle_play_spotify(
     }
     Ok(())
 }
+
+// queues every song of a playlist or album in list order, each one lazily resolving its stream
+// once it gets played, and builds the summary reply
+async fn enqueue_song_list(
+    ctx: Context<'_>,
+    handler_lock: &Mutex<Call>,
+    http_client: Client,
+    credentials: Option<SpotifyCredential>,
+    mut songs: Vec<Song>,
+    url: String,
+) -> CreateReply {
+    songs.sort_by_key(|song| song.list_position);
+
+    let first = songs[0].clone();
+    let list_name = first.list_name.unwrap_or("Unknown".to_string());
+    let list_length = first
+        .list_length
+        .map(|len| len as usize)
+        .unwrap_or(songs.len())
+        .max(songs.len());
+    let max_tracks = ctx.data().app_config.max_tracks_per_command;
+
+    let mut requests = vec![];
+    for song in songs.into_iter().take(max_tracks) {
+        let src = SpotifyDl::from_song(http_client.clone(), song, credentials.clone())
+            .with_tools(
+                ctx.data().app_config.spotdl(),
+                ctx.data().app_config.ytdlp(),
+            )
+            .with_cache(ctx.data().resolve_cache.clone())
+            .with_audio_cache(ctx.data().audio_cache.clone());
+        requests.push(TrackRequest::new(ctx, src).resolve_metadata().await);
+    }
+
+    let mut handler = handler_lock.lock().await;
+    let queued = requests.len();
+    for request in requests {
+        track::enqueue(&mut handler, &ctx.data().player, request).await;
+    }
+
+    println!("current queue length {}", handler.queue().len());
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("Queued {} tracks from {}", queued, list_name))
+        .url(first.list_url.unwrap_or(url))
+        .thumbnail(first.cover_url);
+    if queued < list_length {
+        embed = embed.description(format!(
+            "Only the first {} of {} tracks were queued",
+            queued, list_length
+        ));
+    }
+
+    CreateReply::default().embed(embed).ephemeral(false)
+}
@@ -32,7 +32,7 @@ async fn handle_stop(ctx: Context<'_>, trial_time: i8, max_trial_time: i8) -> Re
             let handler = handler_lock.lock().await;
 
             let queue = handler.queue();
-            let _ = queue.stop();
+            queue.stop();
 
             // handle metadata for spotify adaptor
             ctx.reply("Cleared the queue").await?;
@@ -40,7 +40,7 @@ async fn handle_stop(ctx: Context<'_>, trial_time: i8, max_trial_time: i8) -> Re
         _ => {
             ctx.reply("Not in a voice channel to play in, joining...")
                 .await?;
-            if let Ok(_) = handle_join(ctx).await {
+            if handle_join(ctx).await.is_ok() {
                 let future = Box::pin(handle_stop(ctx, trial_time + 1, max_trial_time));
                 future.await?;
             }
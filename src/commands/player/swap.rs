@@ -0,0 +1,50 @@
+use crate::{player::track, Context, Error};
+use poise::serenity_prelude::CreateEmbed;
+use poise::CreateReply;
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn swap(
+    ctx: Context<'_>,
+    #[description = "Position of the first track"]
+    #[min = 1]
+    a: usize,
+    #[description = "Position of the second track"]
+    #[min = 1]
+    b: usize,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await else {
+        ctx.reply("Not in a voice channel").await?;
+        return Ok(());
+    };
+
+    let swapped = queue.modify_queue(|tracks| {
+        track::check_position(a, tracks.len())?;
+        track::check_position(b, tracks.len())?;
+
+        tracks.swap(a, b);
+        Ok::<_, String>((tracks[a].handle(), tracks[b].handle()))
+    });
+
+    match swapped {
+        Ok((now_a, now_b)) => {
+            let embed = CreateEmbed::new()
+                .title("Swapped in the queue")
+                .description(format!(
+                    "`{}.` {}\n`{}.` {}",
+                    a,
+                    track::title(&now_a).await,
+                    b,
+                    track::title(&now_b).await
+                ));
+            ctx.send(CreateReply::default().embed(embed)).await?;
+        }
+        Err(reason) => {
+            ctx.reply(reason).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::{player::track, Context, Error};
+
+#[poise::command(prefix_command, slash_command)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent"] volume: u16,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let max_volume = ctx.data().app_config.max_volume;
+    if volume > max_volume {
+        ctx.reply(format!("Volume can't go above {}%", max_volume))
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().expect("have guild_id");
+    let guild_settings = &ctx.data().player.guild_settings;
+    guild_settings
+        .update(guild_id, |settings| settings.volume = volume)
+        .await;
+
+    // tracks queued from now on get the volume when they are queued, the ones already
+    // waiting in the queue need it set here
+    if let Some(queue) = track::guild_queue(ctx.serenity_context(), guild_id).await {
+        let scale = guild_settings.get(guild_id).volume_scale();
+        for handle in queue.current_queue() {
+            let _ = handle.set_volume(scale);
+        }
+    }
+
+    ctx.reply(format!("Volume set to {}%", volume)).await?;
+
+    Ok(())
+}
@@ -1,12 +1,17 @@
-use crate::{Context, Error, HttpKey};
-use songbird::input::YoutubeDl;
+use crate::{
+    input::sources::ytdl::YoutubeDl,
+    player::track::{self, TrackRequest},
+    Context, Error, HttpKey,
+};
 
-use super::join::handle_join;
+use super::{autocomplete::autocomplete_song, join::handle_join, query::handle_query_song};
 
 #[poise::command(prefix_command, track_edits, slash_command)]
 pub async fn yt(
     ctx: Context<'_>,
-    #[description = "Url to the song"] url: String,
+    #[description = "Url to the song, or what to search for"]
+    #[autocomplete = "autocomplete_song"]
+    url: String,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
@@ -15,7 +20,7 @@ pub async fn yt(
     Ok(())
 }
 
-async fn handle_play_yt(
+pub async fn handle_play_yt(
     ctx: Context<'_>,
     url: String,
     trial_time: i8,
@@ -27,7 +32,16 @@ async fn handle_play_yt(
         return Ok(());
     }
 
-    let do_search = !url.starts_with("http");
+    // anything but a link is a search, let the user pick which result to play
+    let url = if url.starts_with("http") {
+        url
+    } else {
+        match handle_query_song(ctx, url).await? {
+            Some(url) => url,
+            None => return Ok(()),
+        }
+    };
+
     let ser_ctx = ctx.serenity_context();
     let guild_id = ctx.guild_id().expect("have guild_id");
 
@@ -38,24 +52,6 @@ async fn handle_play_yt(
             .expect("Guaranteed to exist in the typemap.")
     };
 
-    if do_search {
-        println!("searching...");
-        let search_res = YoutubeDl::new_search(http_client.clone(), url.clone())
-            .search(Some(5))
-            .await?;
-
-        println!("search res length {}", search_res.len());
-
-        for res in search_res {
-            match res.title {
-                Some(title) => {
-                    println!("title:{title}");
-                }
-                _ => {}
-            }
-        }
-    }
-
     let manager = songbird::get(ser_ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -63,16 +59,15 @@ async fn handle_play_yt(
 
     match manager.get(guild_id) {
         Some(handler_lock) => {
-            let mut handler = handler_lock.lock().await;
-
-            let src = if do_search {
-                YoutubeDl::new_search(http_client, url)
-            } else {
-                YoutubeDl::new(http_client, url)
-            };
+            let src = YoutubeDl::new(http_client, url)
+                .with_tool(ctx.data().app_config.ytdlp())
+                .with_cache(ctx.data().resolve_cache.clone())
+                .with_audio_cache(ctx.data().audio_cache.clone());
             // let _ = handler.play_input(src.clone().into());
+            let request = TrackRequest::new(ctx, src).resolve_metadata().await;
 
-            handler.enqueue_input(src.into()).await;
+            let mut handler = handler_lock.lock().await;
+            track::enqueue(&mut handler, &ctx.data().player, request).await;
 
             let q_len = handler.queue().len();
             println!("current queue length {}", q_len);
@@ -82,7 +77,7 @@ async fn handle_play_yt(
         _ => {
             ctx.reply("Not in a voice channel to play in, joining...")
                 .await?;
-            if let Ok(_) = handle_join(ctx).await {
+            if handle_join(ctx).await.is_ok() {
                 let future = Box::pin(handle_play_yt(ctx, url, trial_time + 1, max_trial_time));
                 future.await?;
             }
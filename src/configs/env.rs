@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use serde::Deserialize;
 
+use crate::input::process::Tool;
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
     #[serde(with = "rc_string_serde")]
@@ -10,10 +12,124 @@ pub struct Config {
     pub spotify_client_id: Arc<String>,
     #[serde(with = "rc_string_serde")]
     pub spotify_client_secret: Arc<String>,
+    // upper bound of tracks a single command may queue, e.g. when expanding a playlist
+    #[serde(default = "default_max_tracks_per_command")]
+    pub max_tracks_per_command: usize,
+    // seconds to wait in an empty voice channel before leaving it
+    #[serde(default = "default_voice_idle_timeout_secs")]
+    pub voice_idle_timeout_secs: u64,
+    // highest volume in percent /volume accepts
+    #[serde(default = "default_max_volume")]
+    pub max_volume: u16,
+    // seconds an external tool like spotdl or yt-dlp may run before it gets killed
+    #[serde(default = "default_subprocess_timeout_secs")]
+    pub subprocess_timeout_secs: u64,
+    // spotdl executable, either a path or a name looked up on PATH
+    #[serde(default = "default_spotdl_path")]
+    pub spotdl_path: String,
+    // extra arguments passed to every spotdl run, comma separated
+    #[serde(default)]
+    pub spotdl_args: Vec<String>,
+    // yt-dlp executable, either a path or a name looked up on PATH
+    #[serde(default = "default_ytdlp_path")]
+    pub ytdlp_path: String,
+    // extra arguments passed to every yt-dlp run, comma separated
+    #[serde(default)]
+    pub ytdlp_args: Vec<String>,
+    // where per-guild settings such as the volume are kept across restarts
+    #[serde(default = "default_guild_settings_file")]
+    pub guild_settings_file: String,
+    // where resolved songs and stream urls are kept across restarts, only in memory when unset
+    #[serde(default)]
+    pub resolve_cache_file: Option<String>,
+    // where streamed audio is kept to play it from disk next time, not cached when unset
+    #[serde(default)]
+    pub audio_cache_dir: Option<String>,
+    // megabytes, see `MB`, the audio cache may take up before the least recently played
+    // files go
+    #[serde(default = "default_audio_cache_max_mb")]
+    pub audio_cache_max_mb: u64,
+    // whether the next track's stream is opened as soon as it's resolved, rather than a few
+    // seconds before its turn
+    #[serde(default)]
+    pub buffer_next_track: bool,
+    // directory /local plays files from, the command is refused when unset
+    #[serde(default)]
+    pub music_library_dir: Option<String>,
+    // largest attachment in megabytes, see `MB`, "Play attachment" and /play accept
+    #[serde(default = "default_max_attachment_mb")]
+    pub max_attachment_mb: u64,
+}
+
+/// The megabyte every `*_MB` setting counts in, the same one discord's upload limits use.
+pub const MB: u64 = 1024 * 1024;
+
+impl Config {
+    pub fn audio_cache_max_bytes(&self) -> u64 {
+        self.audio_cache_max_mb * MB
+    }
+
+    pub fn max_attachment_bytes(&self) -> u64 {
+        self.max_attachment_mb * MB
+    }
+
+    pub fn spotdl(&self) -> Tool {
+        self.tool(&self.spotdl_path, &self.spotdl_args)
+    }
+
+    pub fn ytdlp(&self) -> Tool {
+        self.tool(&self.ytdlp_path, &self.ytdlp_args)
+    }
+
+    fn tool(&self, program: &str, args: &[String]) -> Tool {
+        Tool {
+            program: program.to_string(),
+            // an empty variable still gives one empty argument
+            args: args.iter().filter(|arg| !arg.is_empty()).cloned().collect(),
+            timeout: Duration::from_secs(self.subprocess_timeout_secs),
+        }
+    }
+}
+
+fn default_max_tracks_per_command() -> usize {
+    50
+}
+
+fn default_voice_idle_timeout_secs() -> u64 {
+    120
+}
+
+fn default_max_volume() -> u16 {
+    200
+}
+
+fn default_subprocess_timeout_secs() -> u64 {
+    90
+}
+
+fn default_spotdl_path() -> String {
+    "spotdl".to_string()
+}
+
+fn default_ytdlp_path() -> String {
+    "yt-dlp".to_string()
+}
+
+fn default_guild_settings_file() -> String {
+    "guild_settings.json".to_string()
+}
+
+fn default_audio_cache_max_mb() -> u64 {
+    2048
+}
+
+fn default_max_attachment_mb() -> u64 {
+    25
 }
+
 // Module containing serialization/deserialization logic
 mod rc_string_serde {
-    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::{Deserialize, Deserializer};
     use std::sync::Arc;
 
     // Serialize just the String contents
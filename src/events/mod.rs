@@ -0,0 +1,26 @@
+pub mod voice_state;
+
+use crate::{Data, Error};
+use poise::serenity_prelude as serenity;
+
+pub async fn handle_event(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        serenity::FullEvent::VoiceStateUpdate { new, .. } => {
+            if let Some(guild_id) = new.guild_id {
+                voice_state::handle_voice_state_update(ctx, guild_id, data).await?;
+            }
+        }
+        _ => {
+            println!(
+                "Got an event in event handler: {:?}",
+                event.snake_case_name()
+            );
+        }
+    }
+
+    Ok(())
+}
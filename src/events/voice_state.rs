@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use crate::{Data, Error};
+use poise::serenity_prelude::{ChannelId, Context, GuildId};
+use songbird::tracks::{PlayMode, TrackQueue};
+use tokio::task::JoinHandle;
+
+/// A disconnect scheduled once the bot's voice channel emptied out.
+pub struct IdleDisconnect {
+    task: JoinHandle<()>,
+    // whether the bot paused playback itself, a track someone paused stays paused
+    resume: bool,
+}
+
+// pauses the queue as soon as nobody is left listening with the bot, and leaves the channel
+// once the grace period ran out without anyone coming back
+pub async fn handle_voice_state_update(
+    ctx: &Context,
+    guild_id: GuildId,
+    data: &Data,
+) -> Result<(), Error> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        return Ok(());
+    };
+
+    let (channel_id, queue) = {
+        let handler = handler_lock.lock().await;
+        match handler.current_channel() {
+            Some(channel_id) => (ChannelId::new(channel_id.0.get()), handler.queue().clone()),
+            None => return Ok(()),
+        }
+    };
+
+    // without the guild in the cache there's no telling whether anyone is left
+    let Some(listeners) = count_listeners(ctx, guild_id, channel_id) else {
+        return Ok(());
+    };
+
+    if listeners > 0 {
+        // someone came back before the bot left, carry on where we stopped
+        let pending = data.idle_disconnects.lock().unwrap().remove(&guild_id);
+        if let Some(pending) = pending.filter(|pending| !pending.task.is_finished()) {
+            pending.task.abort();
+            if pending.resume {
+                let _ = queue.resume();
+            }
+        }
+        return Ok(());
+    }
+
+    let playing = is_playing(&queue).await;
+    {
+        let mut idle_disconnects = data.idle_disconnects.lock().unwrap();
+        if idle_disconnects
+            .get(&guild_id)
+            .is_some_and(|pending| !pending.task.is_finished())
+        {
+            return Ok(());
+        }
+
+        let timeout = Duration::from_secs(data.app_config.voice_idle_timeout_secs);
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+
+            if let Some(handler_lock) = manager.get(guild_id) {
+                handler_lock.lock().await.queue().stop();
+            }
+            if let Err(e) = manager.remove(guild_id).await {
+                println!(
+                    "Failed to leave idle voice channel in guild {}: {:?}",
+                    guild_id, e
+                );
+            }
+        });
+        idle_disconnects.insert(
+            guild_id,
+            IdleDisconnect {
+                task,
+                resume: playing,
+            },
+        );
+    }
+
+    if playing {
+        let _ = queue.pause();
+    }
+
+    Ok(())
+}
+
+async fn is_playing(queue: &TrackQueue) -> bool {
+    let Some(current) = queue.current() else {
+        return false;
+    };
+
+    current
+        .get_info()
+        .await
+        .is_ok_and(|info| matches!(info.playing, PlayMode::Play))
+}
+
+fn count_listeners(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> Option<usize> {
+    let bot_id = ctx.cache.current_user().id;
+
+    let guild = ctx.cache.guild(guild_id)?;
+    let listeners = guild
+        .voice_states
+        .values()
+        .filter(|state| state.channel_id == Some(channel_id))
+        .filter(|state| state.user_id != bot_id)
+        .filter(|state| !state.member.as_ref().is_some_and(|member| member.user.bot))
+        .count();
+
+    Some(listeners)
+}
@@ -0,0 +1,500 @@
+use songbird::input::AudioStream;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use symphonia_core::io::MediaSource;
+use tempfile::NamedTempFile;
+use tokio::runtime::Handle;
+
+#[derive(Debug)]
+struct CachedAudio {
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Audio that was streamed before, kept on disk so popular tracks play from there instead of
+/// being downloaded again. The least recently played files are evicted once the cache grows
+/// past `max_bytes`.
+///
+/// Files are named after their key, last access times live in their modification times so the
+/// eviction order survives restarts.
+#[derive(Debug)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    entries: Mutex<HashMap<String, CachedAudio>>,
+}
+
+fn is_key_safe(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The cache key of a song, by its ISRC which stays the same whichever link it came from.
+pub fn isrc_key(isrc: &str) -> Option<String> {
+    is_key_safe(isrc).then(|| format!("isrc-{}", isrc.to_uppercase()))
+}
+
+/// The cache key of a youtube video, by the id taken from any of the usual link forms.
+pub fn video_key(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/')?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    let id = match host {
+        "youtu.be" => path.split(['?', '&', '#']).next(),
+        "youtube.com" | "m.youtube.com" | "music.youtube.com" => match path.split_once('?') {
+            Some(("watch", params)) => params
+                .split(['&', '#'])
+                .find_map(|param| param.strip_prefix("v=")),
+            _ => path
+                .strip_prefix("shorts/")
+                .or_else(|| path.strip_prefix("embed/"))
+                .and_then(|id| id.split(['?', '&', '#']).next()),
+        },
+        _ => None,
+    }?;
+
+    is_key_safe(id).then(|| format!("youtube-{}", id))
+}
+
+impl AudioCache {
+    /// Opens the cache in `dir`, picking up the files of earlier runs and dropping the ones
+    /// left half written.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut entries = HashMap::new();
+        for file in std::fs::read_dir(&dir)? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                let _ = std::fs::remove_file(file.path());
+                continue;
+            }
+
+            let meta = file.metadata()?;
+            if meta.is_file() {
+                entries.insert(
+                    name,
+                    CachedAudio {
+                        size: meta.len(),
+                        last_used: meta.modified()?,
+                    },
+                );
+            }
+        }
+
+        let cache = Self {
+            dir,
+            max_bytes,
+            entries: Mutex::new(HashMap::new()),
+        };
+        cache.evict(&mut entries, None);
+        *cache.entries.lock().unwrap() = entries;
+
+        Ok(cache)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().contains_key(key)
+    }
+
+    /// Plays the audio stored under `key` from disk, when there is any. The file is opened off
+    /// the runtime's threads.
+    pub async fn stream(self: &Arc<Self>, key: &str) -> Option<AudioStream<Box<dyn MediaSource>>> {
+        if !self.contains(key) {
+            return None;
+        }
+
+        let cache = self.clone();
+        let key = key.to_string();
+        tokio::task::spawn_blocking(move || cache.open_stream(&key))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    fn open_stream(&self, key: &str) -> Option<AudioStream<Box<dyn MediaSource>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(key)?;
+
+        let file = match File::open(self.path(key)) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Dropping unreadable cached audio {}: {}", key, e);
+                entries.remove(key);
+                return None;
+            }
+        };
+        entry.last_used = SystemTime::now();
+        let _ = file.set_modified(entry.last_used);
+
+        Some(AudioStream {
+            input: Box::new(file),
+            hint: None,
+        })
+    }
+
+    /// Passes `stream` through while writing it to a file, which gets stored under `key` once
+    /// the stream was read to the end. Streams that get stopped or skipped through early, or
+    /// that turn out larger than the whole cache, aren't stored.
+    ///
+    /// Must be called from within the tokio runtime, which stores the file in the background.
+    pub fn tee(
+        self: &Arc<Self>,
+        key: String,
+        stream: AudioStream<Box<dyn MediaSource>>,
+    ) -> AudioStream<Box<dyn MediaSource>> {
+        if stream
+            .input
+            .byte_len()
+            .is_some_and(|len| len > self.max_bytes)
+        {
+            return stream;
+        }
+        let Ok(runtime) = Handle::try_current() else {
+            return stream;
+        };
+        let file = match tempfile::Builder::new().prefix(".").tempfile_in(&self.dir) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Not caching {}, no temporary file: {}", key, e);
+                return stream;
+            }
+        };
+
+        AudioStream {
+            input: Box::new(TeeSource {
+                inner: stream.input,
+                pos: 0,
+                tee: Some(Tee {
+                    runtime,
+                    cache: self.clone(),
+                    key,
+                    file,
+                    written: 0,
+                }),
+            }),
+            hint: stream.hint,
+        }
+    }
+
+    fn insert(&self, key: &str, file: NamedTempFile) {
+        let size = match file.as_file().metadata() {
+            Ok(meta) => meta.len(),
+            Err(e) => {
+                println!("Not caching {}: {}", key, e);
+                return;
+            }
+        };
+        if let Err(e) = file.persist(self.path(key)) {
+            println!("Not caching {}: {}", key, e);
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(
+            key.to_string(),
+            CachedAudio {
+                size,
+                last_used: SystemTime::now(),
+            },
+        );
+        self.evict(&mut entries, Some(key));
+    }
+
+    // drops the least recently played files until the rest fits into the budget
+    fn evict(&self, entries: &mut HashMap<String, CachedAudio>, keep: Option<&str>) {
+        let mut total: u64 = entries.values().map(|entry| entry.size).sum();
+        while total > self.max_bytes {
+            let Some(oldest) = entries
+                .iter()
+                .filter(|(key, _)| Some(key.as_str()) != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            let entry = entries.remove(&oldest).expect("key was just found");
+            total -= entry.size;
+            // a guild still playing the file keeps it open, removing it doesn't cut them off
+            if let Err(e) = std::fs::remove_file(self.path(&oldest)) {
+                println!("Failed to evict {} from the audio cache: {}", oldest, e);
+            }
+        }
+    }
+}
+
+struct Tee {
+    // the stream is read on songbird's mixer thread, which has no runtime of its own
+    runtime: Handle,
+    cache: Arc<AudioCache>,
+    key: String,
+    // removed on drop unless persisted, a stopped track leaves nothing behind
+    file: NamedTempFile,
+    written: u64,
+}
+
+/// Copies what is read from `inner` into the cache, see [`AudioCache::tee`].
+struct TeeSource {
+    inner: Box<dyn MediaSource>,
+    pos: u64,
+    tee: Option<Tee>,
+}
+
+impl TeeSource {
+    // persisting and evicting touch the disk, which must not hold up the mixer
+    fn finish(&mut self) {
+        if let Some(Tee {
+            runtime,
+            cache,
+            key,
+            file,
+            ..
+        }) = self.tee.take()
+        {
+            runtime.spawn_blocking(move || cache.insert(&key, file));
+        }
+    }
+}
+
+impl Read for TeeSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let end = self.pos + n as u64;
+
+        if let Some(tee) = &mut self.tee {
+            if self.pos > tee.written {
+                // seeked past what was written, the file would have a hole
+                self.tee = None;
+            } else if end > tee.cache.max_bytes {
+                // streams of unknown length are only found to be too large along the way
+                self.tee = None;
+            } else if end > tee.written {
+                let new = &buf[(tee.written - self.pos) as usize..n];
+                match tee.file.write_all(new) {
+                    Ok(()) => tee.written = end,
+                    Err(e) => {
+                        println!("Not caching {}: {}", tee.key, e);
+                        self.tee = None;
+                    }
+                }
+            }
+        }
+        self.pos = end;
+
+        let complete = match &self.tee {
+            Some(tee) => {
+                (n == 0 && !buf.is_empty() && tee.written == self.pos)
+                    || self.inner.byte_len() == Some(tee.written)
+            }
+            None => false,
+        };
+        if complete {
+            self.finish();
+        }
+
+        Ok(n)
+    }
+}
+
+impl Seek for TeeSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for TeeSource {
+    fn is_seekable(&self) -> bool {
+        self.inner.is_seekable()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.inner.byte_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Cursor, path::Path};
+    use symphonia_core::io::ReadOnlySource;
+
+    fn audio(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn teed(cache: &Arc<AudioCache>, key: &str, data: &[u8]) -> Box<dyn MediaSource> {
+        let stream = AudioStream {
+            input: Box::new(Cursor::new(data.to_vec())) as Box<dyn MediaSource>,
+            hint: None,
+        };
+        cache.tee(key.to_string(), stream).input
+    }
+
+    async fn cached(cache: &Arc<AudioCache>, key: &str) -> Option<Vec<u8>> {
+        let mut data = vec![];
+        cache
+            .stream(key)
+            .await?
+            .input
+            .read_to_end(&mut data)
+            .unwrap();
+        Some(data)
+    }
+
+    fn files_in(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    // waits for a stream read to the end to be stored in the background
+    async fn stored(cache: &AudioCache, key: &str) {
+        for _ in 0..100 {
+            if cache.contains(key) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("{key} was never stored");
+    }
+
+    #[test]
+    fn keys_come_from_isrcs_and_video_ids() {
+        assert_eq!(
+            isrc_key("usrc17607839").as_deref(),
+            Some("isrc-USRC17607839")
+        );
+        assert_eq!(isrc_key(""), None);
+        assert_eq!(isrc_key("../etc"), None);
+
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123",
+            "https://youtube.com/watch?feature=share&v=dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ?si=abc",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(
+                video_key(url).as_deref(),
+                Some("youtube-dQw4w9WgXcQ"),
+                "{url}"
+            );
+        }
+        assert_eq!(video_key("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+        assert_eq!(video_key("never gonna give you up"), None);
+    }
+
+    #[tokio::test]
+    async fn streams_read_to_the_end_are_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(AudioCache::open(dir.path(), 10_000).unwrap());
+        let data = audio(4000);
+
+        let mut input = teed(&cache, "youtube-abc", &data);
+        // probing reads the start again after seeking back
+        let mut head = [0; 100];
+        input.read_exact(&mut head).unwrap();
+        input.seek(SeekFrom::Start(0)).unwrap();
+        let mut played = vec![];
+        input.read_to_end(&mut played).unwrap();
+        assert_eq!(played, data);
+
+        stored(&cache, "youtube-abc").await;
+        assert_eq!(cached(&cache, "youtube-abc").await, Some(data));
+        assert_eq!(files_in(dir.path()), 1);
+    }
+
+    #[tokio::test]
+    async fn stopped_and_skipped_streams_are_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(AudioCache::open(dir.path(), 10_000).unwrap());
+        let data = audio(4000);
+
+        let mut input = teed(&cache, "youtube-stopped", &data);
+        input.read_exact(&mut [0; 100]).unwrap();
+        drop(input);
+
+        let mut input = teed(&cache, "youtube-skipped", &data);
+        input.read_exact(&mut [0; 100]).unwrap();
+        input.seek(SeekFrom::Start(2000)).unwrap();
+        input.read_to_end(&mut vec![]).unwrap();
+        drop(input);
+
+        assert_eq!(cached(&cache, "youtube-stopped").await, None);
+        assert_eq!(cached(&cache, "youtube-skipped").await, None);
+        assert_eq!(files_in(dir.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn streams_of_unknown_length_stay_within_the_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(AudioCache::open(dir.path(), 2500).unwrap());
+        let unknown_length = |data: Vec<u8>| AudioStream {
+            input: Box::new(ReadOnlySource::new(Cursor::new(data))) as Box<dyn MediaSource>,
+            hint: None,
+        };
+
+        let mut input = cache
+            .tee("youtube-long".into(), unknown_length(audio(4000)))
+            .input;
+        let mut played = vec![];
+        input.read_to_end(&mut played).unwrap();
+        assert_eq!(played.len(), 4000);
+        // given up on as soon as it went past the budget, nothing is left on disk
+        assert_eq!(files_in(dir.path()), 0);
+
+        cache
+            .tee("youtube-short".into(), unknown_length(audio(2000)))
+            .input
+            .read_to_end(&mut vec![])
+            .unwrap();
+        stored(&cache, "youtube-short").await;
+        assert!(!cache.contains("youtube-long"));
+    }
+
+    #[tokio::test]
+    async fn least_recently_played_audio_is_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(AudioCache::open(dir.path(), 2500).unwrap());
+
+        for key in ["isrc-A", "isrc-B"] {
+            teed(&cache, key, &audio(1000))
+                .read_to_end(&mut vec![])
+                .unwrap();
+            stored(&cache, key).await;
+        }
+        assert!(cached(&cache, "isrc-A").await.is_some());
+        teed(&cache, "isrc-C", &audio(1000))
+            .read_to_end(&mut vec![])
+            .unwrap();
+        stored(&cache, "isrc-C").await;
+
+        assert!(cached(&cache, "isrc-B").await.is_none());
+        assert!(cached(&cache, "isrc-A").await.is_some());
+        assert!(cached(&cache, "isrc-C").await.is_some());
+        assert_eq!(files_in(dir.path()), 2);
+
+        // a smaller budget on the next start keeps the latest one
+        drop(cache);
+        let cache = Arc::new(AudioCache::open(dir.path(), 1000).unwrap());
+        assert!(cached(&cache, "isrc-A").await.is_none());
+        assert!(cached(&cache, "isrc-C").await.is_some());
+    }
+}
@@ -0,0 +1,462 @@
+use crate::input::{metadata::spotdl::Output, spotify};
+use crate::models::metadata::spotdl::Song;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+
+// metadata hardly ever changes, stream urls are signed and expire within hours
+const METADATA_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const STREAM_URL_TTL: Duration = Duration::from_secs(30 * 60);
+// a song spotdl can't find keeps failing for a while, every source queued for it would
+// otherwise run into the same failure or timeout again
+const FAILURE_TTL: Duration = Duration::from_secs(10 * 60);
+
+// stream urls are dropped this long before they expire, leaving enough time for the track to
+// play through and seek around
+const STREAM_URL_EXPIRY_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+// changes are written back to disk at most this often, see `ResolveCache::spawn_saver`
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+enum Resolved {
+    Songs(Vec<Song>),
+    Outputs(Vec<Output>),
+    StreamUrl(String),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    resolved: Resolved,
+    // unix seconds
+    expires_at: u64,
+}
+
+/// What spotdl and yt-dlp resolved queries to, shared by every guild so playing the same song
+/// again doesn't spawn them again. Entries are written back to `path`, when there is one, so
+/// they survive restarts, see [`flush`].
+///
+/// [`flush`]: Self::flush
+#[derive(Debug, Default)]
+pub struct ResolveCache {
+    path: Option<PathBuf>,
+    entries: RwLock<HashMap<String, Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    // whether entries changed since they were last written to `path`
+    dirty: AtomicBool,
+    // held while writing to `path`, a periodic save may run into the one at shutdown
+    saving: Mutex<()>,
+}
+
+/// How well the [`ResolveCache`] is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The unix time a signed stream url stops working at, googlevideo urls carry it in their
+/// `expire` parameter.
+pub fn url_expiry(url: &str) -> Option<u64> {
+    url.split(['?', '&'])
+        .find_map(|param| param.strip_prefix("expire="))
+        .and_then(|expire| expire.parse().ok())
+}
+
+/// Whether a signed stream url stops working soon, urls without an expiry are assumed to stay
+/// valid.
+pub fn expires_soon(url: &str) -> bool {
+    url_expiry(url).is_some_and(|expire| expire < unix_now() + STREAM_URL_EXPIRY_MARGIN.as_secs())
+}
+
+// the same song is often asked for in different ways, e.g. with a different `si` tracking
+// parameter or in different case
+fn normalize(query: &str) -> String {
+    if let Some(uri) = spotify::canonical_uri(query) {
+        return uri;
+    }
+
+    let query = query.trim();
+    if query.starts_with("http://") || query.starts_with("https://") {
+        return query.to_string();
+    }
+
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl ResolveCache {
+    /// Loads the entries saved at `path` that didn't expire yet, starting over when there are
+    /// none.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut entries: HashMap<String, Entry> = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                println!("Ignoring unreadable resolve cache {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let now = unix_now();
+        entries.retain(|_, entry| entry.expires_at > now);
+
+        Self {
+            path: Some(path),
+            entries: RwLock::new(entries),
+            ..Default::default()
+        }
+    }
+
+    /// Songs spotdl or the Spotify Web API found for `query`.
+    pub fn songs(&self, query: &str) -> Option<Vec<Song>> {
+        self.get(
+            &format!("songs:{}", normalize(query)),
+            |resolved| match resolved {
+                Resolved::Songs(songs) => Some(songs.clone()),
+                _ => None,
+            },
+        )
+    }
+
+    pub fn insert_songs(&self, query: &str, songs: &[Song]) {
+        let key = format!("songs:{}", normalize(query));
+        self.insert(key, Resolved::Songs(songs.to_vec()), METADATA_TTL);
+    }
+
+    /// What yt-dlp answered to `query` when asked for `n_results` results. Their stream urls
+    /// may have expired in the meantime.
+    pub fn outputs(&self, query: &str, n_results: usize) -> Option<Vec<Output>> {
+        let key = format!("outputs:{}:{}", n_results, normalize(query));
+        self.get(&key, |resolved| match resolved {
+            Resolved::Outputs(outputs) => Some(outputs.clone()),
+            _ => None,
+        })
+    }
+
+    /// What yt-dlp answered to `query` when asked for its best match, as long as the stream
+    /// url stays valid for a while. Urls about to expire, or not telling when they do, are
+    /// misses since yt-dlp has to run again for them.
+    pub fn playable_outputs(&self, query: &str) -> Option<Vec<Output>> {
+        let key = format!("outputs:1:{}", normalize(query));
+        self.get(&key, |resolved| match resolved {
+            Resolved::Outputs(outputs)
+                if outputs.first().is_some_and(|out| {
+                    url_expiry(&out.url).is_some() && !expires_soon(&out.url)
+                }) =>
+            {
+                Some(outputs.clone())
+            }
+            _ => None,
+        })
+    }
+
+    pub fn insert_outputs(&self, query: &str, n_results: usize, outputs: &[Output]) {
+        let key = format!("outputs:{}:{}", n_results, normalize(query));
+        self.insert(key, Resolved::Outputs(outputs.to_vec()), METADATA_TTL);
+    }
+
+    /// The stream url spotdl found for `query`, as long as it stays valid for a while.
+    pub fn stream_url(&self, query: &str) -> Option<String> {
+        self.get(
+            &format!("stream:{}", normalize(query)),
+            |resolved| match resolved {
+                Resolved::StreamUrl(url) => Some(url.clone()),
+                _ => None,
+            },
+        )
+    }
+
+    /// Drops the stream url of `query`, e.g. once it got rejected before expiring.
+    pub fn remove_stream_url(&self, query: &str) {
+        let mut entries = self.entries.write().unwrap();
+        if entries
+            .remove(&format!("stream:{}", normalize(query)))
+            .is_some()
+        {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn insert_stream_url(&self, query: &str, url: &str) {
+        let ttl = match url_expiry(url) {
+            Some(expire) => Duration::from_secs(expire.saturating_sub(unix_now()))
+                .saturating_sub(STREAM_URL_EXPIRY_MARGIN)
+                .min(STREAM_URL_TTL),
+            None => STREAM_URL_TTL,
+        };
+        if ttl.is_zero() {
+            return;
+        }
+
+        let key = format!("stream:{}", normalize(query));
+        self.insert(key, Resolved::StreamUrl(url.to_string()), ttl);
+    }
+
+    /// Why spotdl failed to resolve `query` lately, if it did. Unlike the other lookups this
+    /// one doesn't count towards the [`stats`], it never saves running spotdl.
+    ///
+    /// [`stats`]: Self::stats
+    pub fn failure(&self, query: &str) -> Option<String> {
+        self.lookup(
+            &format!("failed:{}", normalize(query)),
+            |resolved| match resolved {
+                Resolved::Failed(reason) => Some(reason.clone()),
+                _ => None,
+            },
+        )
+    }
+
+    pub fn insert_failure(&self, query: &str, reason: &str) {
+        let key = format!("failed:{}", normalize(query));
+        self.insert(key, Resolved::Failed(reason.to_string()), FAILURE_TTL);
+    }
+
+    // looks `key` up, counting whether that saved running spotdl or yt-dlp
+    fn get<T>(&self, key: &str, extract: impl FnOnce(&Resolved) -> Option<T>) -> Option<T> {
+        let found = self.lookup(key, extract);
+
+        let counter = match found {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        found
+    }
+
+    fn lookup<T>(&self, key: &str, extract: impl FnOnce(&Resolved) -> Option<T>) -> Option<T> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(key)
+            .filter(|entry| entry.expires_at > unix_now())
+            .and_then(|entry| extract(&entry.resolved))
+    }
+
+    fn insert(&self, key: String, resolved: Resolved, ttl: Duration) {
+        let mut entries = self.entries.write().unwrap();
+        let now = unix_now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(
+            key,
+            Entry {
+                resolved,
+                expires_at: now + ttl.as_secs(),
+            },
+        );
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// How many entries are cached and how many lookups they answered so far.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.read().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Flushes the cache every so often for as long as it's in use elsewhere, so lookups
+    /// never wait on the disk.
+    pub fn spawn_saver(self: &Arc<Self>) -> JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => cache.flush().await,
+                    None => return,
+                }
+            }
+        })
+    }
+
+    /// Writes the entries back to `path` if they changed since the last time, off the
+    /// runtime's threads.
+    pub async fn flush(self: &Arc<Self>) {
+        if self.path.is_none() || !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        let cache = self.clone();
+        let saved = match tokio::task::spawn_blocking(move || cache.save()).await {
+            Ok(saved) => saved,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = saved {
+            println!("Failed to save resolve cache: {}", e);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let _saving = self.saving.lock().unwrap();
+        // lookups only wait for the copy, not for the serializing
+        let entries = self.entries.read().unwrap().clone();
+        let contents = serde_json::to_vec(&entries)?;
+        // write next to the file and swap it in, a crash mid-write must not lose everything
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(url: &str) -> Output {
+        serde_json::from_value(serde_json::json!({ "title": "song", "url": url })).unwrap()
+    }
+
+    fn stream_url(expires_in: u64) -> String {
+        format!(
+            "https://rr1.googlevideo.com/videoplayback?expire={}&id=abc",
+            unix_now() + expires_in
+        )
+    }
+
+    #[test]
+    fn queries_are_normalized() {
+        let cache = ResolveCache::default();
+        cache.insert_outputs("Never  Gonna Give You Up ", 1, &[output("a")]);
+
+        assert!(cache.outputs("never gonna give you up", 1).is_some());
+        assert!(cache.outputs("never gonna give you up", 5).is_none());
+
+        cache.insert_stream_url(
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=123",
+            "https://example.invalid/stream",
+        );
+        assert!(cache
+            .stream_url("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC?si=456")
+            .is_some());
+        assert!(cache
+            .stream_url("spotify:track:4uLU6hMCjMI75M1A2tKUQC")
+            .is_some());
+    }
+
+    #[test]
+    fn stream_urls_respect_their_expiry() {
+        let cache = ResolveCache::default();
+
+        cache.insert_stream_url("expiring", &stream_url(5 * 60));
+        assert!(cache.stream_url("expiring").is_none());
+
+        cache.insert_stream_url("valid", &stream_url(6 * 60 * 60));
+        assert!(cache.stream_url("valid").is_some());
+        let expires_at = cache.entries.read().unwrap()["stream:valid"].expires_at;
+        assert!(expires_at <= unix_now() + STREAM_URL_TTL.as_secs());
+
+        cache.remove_stream_url("valid");
+        assert!(cache.stream_url("valid").is_none());
+    }
+
+    #[test]
+    fn failures_are_remembered_for_a_while() {
+        let cache = ResolveCache::default();
+        assert!(cache.failure("song").is_none());
+
+        cache.insert_failure("Song ", "spotdl timed out");
+        assert_eq!(cache.failure("song").as_deref(), Some("spotdl timed out"));
+        // a failure is no stream url or metadata
+        assert!(cache.stream_url("song").is_none());
+        let expires_at = cache.entries.read().unwrap()["failed:song"].expires_at;
+        assert!(expires_at <= unix_now() + FAILURE_TTL.as_secs());
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let cache = ResolveCache::default();
+        cache.insert_outputs("song", 1, &[output("a")]);
+        cache
+            .entries
+            .write()
+            .unwrap()
+            .get_mut("outputs:1:song")
+            .unwrap()
+            .expires_at = unix_now() - 1;
+
+        assert!(cache.outputs("song", 1).is_none());
+        cache.insert_outputs("other song", 1, &[output("b")]);
+        assert_eq!(cache.entries.read().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn entries_survive_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolve_cache.json");
+
+        let cache = Arc::new(ResolveCache::load(&path));
+        cache.insert_outputs("song", 1, &[output("https://example.invalid/a")]);
+        cache.insert_stream_url("song", "https://example.invalid/stream");
+        // lookups never write to disk themselves
+        assert!(!path.exists());
+        cache.flush().await;
+        drop(cache);
+
+        let cache = ResolveCache::load(&path);
+        let outputs = cache.outputs("song", 1).unwrap();
+        assert_eq!(outputs[0].url, "https://example.invalid/a");
+        assert_eq!(
+            cache.stream_url("song").as_deref(),
+            Some("https://example.invalid/stream")
+        );
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(ResolveCache::load(&path).outputs("song", 1).is_none());
+    }
+
+    #[test]
+    fn hits_and_misses_are_counted() {
+        let cache = ResolveCache::default();
+        assert!(cache.songs("song").is_none());
+        cache.insert_songs("song", &[]);
+        assert!(cache.songs("song").is_some());
+        assert!(cache.songs("Song").is_some());
+        // nothing was saved by finding out spotdl failed before
+        cache.insert_failure("other song", "spotdl timed out");
+        assert!(cache.failure("other song").is_some());
+        // nor by a stream url that has to be looked up again
+        cache.insert_outputs("video", 1, &[output(&stream_url(60))]);
+        assert!(cache.playable_outputs("video").is_none());
+        cache.insert_outputs("other video", 1, &[output(&stream_url(6 * 60 * 60))]);
+        assert!(cache.playable_outputs("other video").is_some());
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                entries: 4,
+                hits: 3,
+                misses: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+// what symphonia and songbird can play with the formats enabled in Cargo.toml, anything else in
+// the library is skipped
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "flac", "m4a", "mka", "mkv", "mp3", "mp4", "oga", "ogg", "opus", "wav", "webm",
+];
+
+/// The music directory `/local` plays from. Paths are always resolved inside of it, links or
+/// `..` leading out of it are refused.
+#[derive(Clone, Debug)]
+pub struct Library {
+    root: PathBuf,
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn has_wildcards(segment: &str) -> bool {
+    segment.contains(['*', '?'])
+}
+
+// matches a single file name against a pattern where `*` stands for any run of characters and
+// `?` for any single one
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where the last `*` was seen and how much of the name it swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Library {
+    pub fn open(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", root.display()),
+            ));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Finds the audio files `pattern` points at, sorted by path. The pattern is a path
+    /// relative to the library, whose segments may contain `*` and `?` wildcards, directories
+    /// stand for every audio file below them.
+    pub async fn find(&self, pattern: &str) -> Result<Vec<PathBuf>, String> {
+        // the walk may go through a large tree, keep it off the runtime's threads
+        let library = self.clone();
+        let pattern = pattern.to_string();
+        tokio::task::spawn_blocking(move || library.search(&pattern))
+            .await
+            .map_err(|e| format!("Searching the music library failed: {}", e))?
+    }
+
+    fn search(&self, pattern: &str) -> Result<Vec<PathBuf>, String> {
+        let pattern = pattern.trim();
+        let relative = match Path::new(pattern).strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) if Path::new(pattern).is_absolute() => {
+                return Err(format!("{} is outside the music library", pattern))
+            }
+            Err(_) => Path::new(pattern),
+        };
+
+        let mut candidates = vec![self.root.clone()];
+        for component in relative.components() {
+            let segment = match component {
+                Component::Normal(segment) => segment.to_string_lossy(),
+                Component::ParentDir => "..".into(),
+                _ => continue,
+            };
+
+            candidates = if has_wildcards(&segment) {
+                candidates
+                    .iter()
+                    .filter_map(|dir| std::fs::read_dir(dir).ok())
+                    .flatten()
+                    .filter_map(Result::ok)
+                    .filter(|entry| {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        // hidden files only show up when asked for explicitly
+                        (!name.starts_with('.') || segment.starts_with('.'))
+                            && matches(&segment, &name)
+                    })
+                    .map(|entry| entry.path())
+                    .collect()
+            } else {
+                candidates
+                    .iter()
+                    .map(|dir| dir.join(segment.as_ref()))
+                    .filter(|path| path.exists())
+                    .collect()
+            };
+        }
+
+        let mut files = vec![];
+        let mut visited = HashSet::new();
+        let mut outside = false;
+        for candidate in candidates {
+            match self.inside(&candidate) {
+                Some(path) if path.is_dir() => self.collect(&path, &mut files, &mut visited),
+                Some(path) if is_audio(&path) => files.push(path),
+                Some(_) => {}
+                None => outside = true,
+            }
+        }
+
+        if files.is_empty() {
+            return Err(if outside {
+                format!("{} is outside the music library", pattern)
+            } else {
+                format!("No audio files in the music library match {}", pattern)
+            });
+        }
+
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    // where `path` really is, as long as that's inside the library
+    fn inside(&self, path: &Path) -> Option<PathBuf> {
+        path.canonicalize()
+            .ok()
+            .filter(|path| path.starts_with(&self.root))
+    }
+
+    fn collect(&self, dir: &Path, files: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+        // links back up the tree would send the walk around in circles
+        if !visited.insert(dir.to_path_buf()) {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match self.inside(&entry.path()) {
+                Some(path) if path.is_dir() => self.collect(&path, files, visited),
+                Some(path) if is_audio(&path) => files.push(path),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // root/
+    //   album/01 intro.mp3, 02 song.flac, cover.jpg, disc 2/01 bonus.ogg
+    //   single.MP3
+    //   .hidden.mp3
+    // outside/secret.mp3, linked into the library as root/escape
+    fn library() -> (tempfile::TempDir, Library) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        for file in [
+            "album/01 intro.mp3",
+            "album/02 song.flac",
+            "album/cover.jpg",
+            "album/disc 2/01 bonus.ogg",
+            "single.MP3",
+            ".hidden.mp3",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::create_dir_all(dir.path().join("outside")).unwrap();
+        std::fs::write(dir.path().join("outside/secret.mp3"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("outside"), root.join("escape")).unwrap();
+
+        let library = Library::open(&root).unwrap();
+        (dir, library)
+    }
+
+    async fn names(library: &Library, pattern: &str) -> Vec<String> {
+        library
+            .find(pattern)
+            .await
+            .unwrap()
+            .iter()
+            .map(|path| {
+                path.strip_prefix(&library.root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wildcards_match_file_names() {
+        assert!(matches("*.mp3", "song.mp3"));
+        assert!(matches("0?-*", "01-intro.flac"));
+        assert!(matches("*a*b*", "xxaxxbxx"));
+        assert!(!matches("*.mp3", "song.flac"));
+        assert!(!matches("0?", "1"));
+    }
+
+    #[tokio::test]
+    async fn finds_files_directories_and_globs() {
+        let (_dir, library) = library();
+
+        assert_eq!(names(&library, "single.MP3").await, ["single.MP3"]);
+        assert_eq!(
+            names(&library, "album").await,
+            [
+                "album/01 intro.mp3",
+                "album/02 song.flac",
+                "album/disc 2/01 bonus.ogg"
+            ]
+        );
+        assert_eq!(
+            names(&library, "*/0*").await,
+            ["album/01 intro.mp3", "album/02 song.flac"]
+        );
+        assert_eq!(names(&library, "*.MP3").await, ["single.MP3"]);
+        assert!(library.find("*.wav").await.is_err());
+        assert_eq!(names(&library, ".hid*").await, [".hidden.mp3"]);
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_the_library() {
+        let (dir, library) = library();
+
+        for pattern in ["../outside/secret.mp3", "escape", "escape/*", "album/../.."] {
+            let e = library.find(pattern).await.unwrap_err();
+            assert!(e.contains("outside the music library"), "{pattern}: {e}");
+        }
+        let absolute = dir.path().join("outside/secret.mp3");
+        assert!(library.find(&absolute.to_string_lossy()).await.is_err());
+
+        // the root itself is fine, minus what links out of it
+        assert_eq!(names(&library, ".").await.len(), 4);
+        let inside = library.root.join("single.MP3");
+        assert_eq!(
+            library.find(&inside.to_string_lossy()).await.unwrap(),
+            [inside]
+        );
+        assert!(library.find("album/cover.jpg").await.is_err());
+    }
+}
@@ -1,9 +1,10 @@
+use crate::models::metadata::spotdl::Song;
 use serde::{Deserialize, Serialize};
 use songbird::constants::SAMPLE_RATE_RAW;
 use songbird::input::AuxMetadata;
 use std::{collections::HashMap, time::Duration};
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Output {
     pub artist: Option<String>,
     pub album: Option<String>,
@@ -22,6 +23,26 @@ pub struct Output {
 }
 
 impl Output {
+    /// Builds the output for a song resolved by spotdl, `url` being the stream to play.
+    pub fn from_song(song: &Song, url: String) -> Self {
+        Output {
+            artist: Some(song.artist.clone()),
+            album: Some(song.album_name.clone()),
+            channel: None,
+            duration: Some(song.duration as f64),
+            filesize: None,
+            http_headers: None,
+            release_date: Some(song.date.clone()),
+            thumbnail: Some(song.cover_url.clone()),
+            title: Some(song.name.clone()),
+            track: Some(song.track_number.to_string()),
+            upload_date: None,
+            uploader: None,
+            url,
+            webpage_url: Some(song.url.clone()),
+        }
+    }
+
     pub fn as_aux_metadata(&self) -> AuxMetadata {
         let album = self.album.clone();
         let track = self.track.clone();
@@ -1,2 +1,7 @@
+pub mod audio_cache;
+pub mod cache;
+pub mod library;
 pub mod metadata;
+pub mod process;
 pub mod sources;
+pub mod spotify;
@@ -0,0 +1,300 @@
+use songbird::input::AudioStreamError;
+use std::{
+    io::ErrorKind,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::process::Command;
+
+// how long a tool may run unless configured otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(90);
+
+// how much of the tail of stderr ends up in error messages
+const STDERR_TAIL_LIMIT: usize = 1000;
+
+/// Kills a whole process group when dropped, so the helpers a tool spawned (ffmpeg, python
+/// workers, ...) don't outlive it.
+struct ProcessGroup(Option<u32>);
+
+impl ProcessGroup {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.0 {
+            // safety: only sends a signal, a group that's already gone just makes it fail
+            unsafe {
+                libc::kill(-(pgid as i32), libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// An external program such as spotdl or yt-dlp, along with the extra arguments passed to
+/// every run of it.
+#[derive(Clone, Debug)]
+pub struct Tool {
+    pub program: String,
+    pub args: Vec<String>,
+    pub timeout: Duration,
+}
+
+impl Tool {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Runs the tool as in [`run`], with its extra arguments appended to `args`.
+    pub async fn run(&self, args: &[&str]) -> Result<Vec<u8>, AudioStreamError> {
+        let args: Vec<&str> = args
+            .iter()
+            .copied()
+            .chain(self.args.iter().map(String::as_str))
+            .collect();
+
+        run(&self.program, &args, self.timeout).await
+    }
+
+    /// Finds the executable the tool runs, a program given as a bare name is looked up on
+    /// `PATH` like the shell would.
+    pub fn locate(&self) -> Result<PathBuf, String> {
+        let is_executable = |path: &Path| {
+            path.metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        };
+
+        if self.program.contains('/') {
+            let path = PathBuf::from(&self.program);
+            return if is_executable(&path) {
+                Ok(path)
+            } else {
+                Err(format!("'{}' is not an executable file", self.program))
+            };
+        }
+
+        std::env::var_os("PATH")
+            .iter()
+            .flat_map(std::env::split_paths)
+            .map(|dir| dir.join(&self.program))
+            .find(|path| is_executable(path))
+            .ok_or_else(|| format!("could not find executable '{}' on path", self.program))
+    }
+}
+
+/// Runs `program` with `args` to completion, returning what it wrote to stdout.
+///
+/// The program gets its own process group which is killed once `timeout` passed or the
+/// returned future is dropped. A non-zero exit fails with the tail of its stderr.
+pub async fn run(
+    program: &str,
+    args: &[&str],
+    timeout: Duration,
+) -> Result<Vec<u8>, AudioStreamError> {
+    // tokio only offers process groups as an unstable feature
+    let mut command = std::process::Command::new(program);
+    command.process_group(0);
+
+    let child = Command::from(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            AudioStreamError::Fail(if e.kind() == ErrorKind::NotFound {
+                format!("could not find executable '{}' on path", program).into()
+            } else {
+                Box::new(e)
+            })
+        })?;
+    let group = ProcessGroup(child.id());
+
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output.map_err(|e| AudioStreamError::Fail(Box::new(e)))?,
+        Err(_) => {
+            println!("{} timed out after {:?}, killing it", program, timeout);
+            return Err(AudioStreamError::Fail(
+                format!("{} timed out after {:?}", program, timeout).into(),
+            ));
+        }
+    };
+    group.disarm();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        let tail_start = stderr
+            .char_indices()
+            .rev()
+            .nth(STDERR_TAIL_LIMIT - 1)
+            .map_or(0, |(i, _)| i);
+        println!("{} failed with {}: {}", program, output.status, stderr);
+
+        return Err(AudioStreamError::Fail(
+            format!(
+                "{} failed with {}: {}",
+                program,
+                output.status,
+                if stderr.is_empty() {
+                    "<no error message>"
+                } else {
+                    &stderr[tail_start..]
+                }
+            )
+            .into(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(dir: &Path, name: &str, body: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // a script that starts a helper in the background, like yt-dlp running ffmpeg, and then
+    // hangs, the helper's pid ends up in `helper.pid`
+    fn hanging_script(dir: &Path) -> (String, std::path::PathBuf) {
+        let pid_file = dir.join("helper.pid");
+        let program = script(
+            dir,
+            "hang",
+            &format!("sleep 30 &\necho $! > \"{}\"\nsleep 30", pid_file.display()),
+        );
+        (program, pid_file)
+    }
+
+    async fn helper_pid(pid_file: &Path) -> String {
+        for _ in 0..50 {
+            if let Ok(pid) = std::fs::read_to_string(pid_file) {
+                if !pid.trim().is_empty() {
+                    return pid.trim().to_string();
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("helper never started");
+    }
+
+    // zombies waiting to be reaped count as gone
+    async fn assert_killed(pid: &str) {
+        for _ in 0..50 {
+            match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+                Err(_) => return,
+                Ok(stat) if stat.contains(") Z") => return,
+                Ok(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        panic!("process {pid} is still running");
+    }
+
+    #[tokio::test]
+    async fn returns_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = script(dir.path(), "ok", "echo \"hello $1\"");
+
+        let stdout = run(&program, &["world"], Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(stdout, b"hello world\n");
+    }
+
+    #[tokio::test]
+    async fn crashes_report_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = script(
+            dir.path(),
+            "crash",
+            "echo 'ERROR: video unavailable' >&2\nexit 3",
+        );
+
+        let e = run(&program, &[], Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        let message = e.to_string();
+        assert!(message.contains("exit status: 3"), "{message}");
+        assert!(message.contains("ERROR: video unavailable"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn tools_append_their_extra_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = script(dir.path(), "args", "echo \"$@\"");
+
+        let tool = Tool {
+            args: vec!["--proxy".into(), "socks5://localhost:1080".into()],
+            ..Tool::new(&program)
+        };
+        let stdout = tool.run(&["url", "some song"]).await.unwrap();
+        assert_eq!(stdout, b"url some song --proxy socks5://localhost:1080\n");
+    }
+
+    #[test]
+    fn tools_are_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = script(dir.path(), "spotdl", "exit 0");
+
+        assert_eq!(Tool::new(&program).locate(), Ok(PathBuf::from(&program)));
+        assert!(Tool::new("sh").locate().is_ok());
+        assert!(Tool::new("definitely-not-installed-tool").locate().is_err());
+
+        let not_executable = dir.path().join("notes.txt");
+        std::fs::write(&not_executable, "").unwrap();
+        assert!(Tool::new(not_executable.to_string_lossy())
+            .locate()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_programs_are_reported() {
+        let e = run("/nonexistent/yt-dlp", &[], Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("could not find executable"));
+    }
+
+    #[tokio::test]
+    async fn timeouts_kill_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let (program, pid_file) = hanging_script(dir.path());
+
+        let started = std::time::Instant::now();
+        let e = run(&program, &[], Duration::from_millis(500))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert_killed(&helper_pid(&pid_file).await).await;
+    }
+
+    #[tokio::test]
+    async fn dropping_the_future_kills_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let (program, pid_file) = hanging_script(dir.path());
+
+        let run = run(&program, &[], Duration::from_secs(60));
+        assert!(tokio::time::timeout(Duration::from_millis(500), run)
+            .await
+            .is_err());
+
+        assert_killed(&helper_pid(&pid_file).await).await;
+    }
+}
@@ -0,0 +1,244 @@
+use crate::configs::env::MB;
+use poise::serenity_prelude::{async_trait, Attachment};
+use reqwest::Client;
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, HttpRequest, Input};
+use std::{path::Path, time::Duration};
+use symphonia_core::io::MediaSource;
+
+// what discord users commonly upload, and the symphonia formats enabled in Cargo.toml can decode
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "m4a", "wav"];
+
+/// An audio file attached to a discord message, streamed from discord's cdn with songbird's
+/// [`HttpRequest`].
+#[derive(Clone, Debug)]
+pub struct AttachmentFile {
+    request: HttpRequest,
+    metadata: AuxMetadata,
+}
+
+/// Checks an attachment looks like audio we can play before anything gets downloaded.
+/// Discord tells the content type for most uploads, the extension has to fit either way.
+pub fn check_attachment(
+    filename: &str,
+    content_type: Option<&str>,
+    size: u64,
+    max_bytes: u64,
+) -> Result<(), String> {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if !extension.is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str())) {
+        return Err(format!(
+            "{} is not an audio file, only {} files can be played",
+            filename,
+            AUDIO_EXTENSIONS.join(", ")
+        ));
+    }
+
+    // parameters like "; charset=utf-8" don't matter
+    let mime = content_type.map(|mime| mime.split(';').next().unwrap_or_default().trim());
+    if let Some(mime) =
+        mime.filter(|mime| !mime.starts_with("audio/") && *mime != "application/ogg")
+    {
+        return Err(format!("{} is {}, not audio", filename, mime));
+    }
+
+    if size > max_bytes {
+        return Err(format!(
+            "{} is {:.1} MB, files up to {} MB can be played",
+            filename,
+            size as f64 / MB as f64,
+            max_bytes / MB
+        ));
+    }
+
+    Ok(())
+}
+
+impl AttachmentFile {
+    /// Creates a lazy request for `attachment`, as long as it passes [`check_attachment`].
+    pub fn new(client: Client, attachment: &Attachment, max_bytes: u64) -> Result<Self, String> {
+        check_attachment(
+            &attachment.filename,
+            attachment.content_type.as_deref(),
+            attachment.size.into(),
+            max_bytes,
+        )?;
+
+        let mut request = HttpRequest::new(client, attachment.url.clone());
+        // knowing the length lets songbird seek with range requests
+        request.content_length = Some(attachment.size.into());
+
+        Ok(Self {
+            request,
+            metadata: AuxMetadata {
+                title: Path::new(&attachment.filename)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned()),
+                duration: attachment.duration_secs.map(Duration::from_secs_f64),
+                source_url: Some(attachment.url.clone()),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+impl From<AttachmentFile> for Input {
+    fn from(val: AttachmentFile) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for AttachmentFile {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.request.create_async().await
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        Ok(self.metadata.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use songbird::input::codecs::{CODEC_REGISTRY, PROBE};
+    use std::io::Cursor;
+    use symphonia::core::{codecs::CODEC_TYPE_AAC, io::MediaSourceStream, probe::Hint};
+
+    const MAX_BYTES: u64 = 25 * MB;
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut atom = (8 + body.len() as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(body);
+        atom
+    }
+
+    // an atom whose fields start with a version and flags, all zero here
+    fn full_atom(kind: &[u8; 4], fields: &[u8]) -> Vec<u8> {
+        atom(kind, &[&[0; 4], fields].concat())
+    }
+
+    // the smallest m4a the mp4 demuxer accepts: one aac track without any samples
+    fn empty_m4a() -> Vec<u8> {
+        let rate = 44_100u32.to_be_bytes();
+        // aac lc at 44.1 kHz in stereo, wrapped in the mpeg-4 descriptors
+        let esds = full_atom(
+            b"esds",
+            &[
+                &[3, 25, 0, 0, 0][..],
+                &[4, 17, 0x40, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                &[5, 2, 0x12, 0x10],
+                &[6, 1, 2],
+            ]
+            .concat(),
+        );
+        let mp4a = atom(
+            b"mp4a",
+            &[
+                &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0][..],
+                &[0, 2, 0, 16, 0, 0, 0, 0],
+                &(44_100u32 << 16).to_be_bytes(),
+                &esds,
+            ]
+            .concat(),
+        );
+        let stbl = [
+            full_atom(b"stsd", &[&[0, 0, 0, 1][..], &mp4a].concat()),
+            full_atom(b"stts", &[0; 4]),
+            full_atom(b"stsc", &[0; 4]),
+            full_atom(b"stsz", &[0; 8]),
+            full_atom(b"stco", &[0; 4]),
+        ]
+        .concat();
+        let minf = [full_atom(b"smhd", &[0; 4]), atom(b"stbl", &stbl)].concat();
+        let mdia = [
+            full_atom(
+                b"mdhd",
+                &[&[0; 8][..], &rate, &[0; 4], &[0x55, 0xc4, 0, 0]].concat(),
+            ),
+            full_atom(
+                b"hdlr",
+                &[&[0; 4][..], b"soun", &[0; 12], b"sound\0"].concat(),
+            ),
+            atom(b"minf", &minf),
+        ]
+        .concat();
+        let tkhd = [&[0; 8][..], &[0, 0, 0, 1], &[0; 20], &[1, 0]].concat();
+        let trak = [full_atom(b"tkhd", &tkhd), atom(b"mdia", &mdia)].concat();
+        let mvhd = [&[0; 8][..], &rate, &[0; 4], &[0, 1, 0, 0, 1, 0]].concat();
+        let moov = [full_atom(b"mvhd", &mvhd), atom(b"trak", &trak)].concat();
+        [
+            atom(b"ftyp", b"M4A \0\0\0\0M4A isom"),
+            atom(b"moov", &moov),
+            atom(b"mdat", &[]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn audio_attachments_pass() {
+        for (filename, content_type) in [
+            ("song.mp3", Some("audio/mpeg")),
+            ("Song.FLAC", Some("audio/flac")),
+            ("voice.ogg", Some("audio/ogg; codecs=opus")),
+            ("track.m4a", Some("audio/x-m4a")),
+            ("take.wav", None),
+        ] {
+            assert_eq!(
+                check_attachment(filename, content_type, 1_000_000, MAX_BYTES),
+                Ok(()),
+                "{filename}"
+            );
+        }
+    }
+
+    #[test]
+    fn m4a_attachments_can_be_decoded() {
+        let mut hint = Hint::new();
+        hint.with_extension("m4a");
+        let stream = MediaSourceStream::new(Box::new(Cursor::new(empty_m4a())), Default::default());
+        let probed = PROBE
+            .format(&hint, stream, &Default::default(), &Default::default())
+            .expect("the mp4 demuxer is enabled");
+
+        let track = probed.format.default_track().unwrap();
+        assert_eq!(track.codec_params.codec, CODEC_TYPE_AAC);
+        assert!(CODEC_REGISTRY
+            .make(&track.codec_params, &Default::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn other_attachments_are_refused() {
+        let e = check_attachment("cat.png", Some("image/png"), 1000, MAX_BYTES).unwrap_err();
+        assert!(e.contains("not an audio file"), "{e}");
+
+        let e = check_attachment("notes", None, 1000, MAX_BYTES).unwrap_err();
+        assert!(e.contains("not an audio file"), "{e}");
+
+        let e = check_attachment("fake.mp3", Some("text/html"), 1000, MAX_BYTES).unwrap_err();
+        assert!(e.contains("text/html"), "{e}");
+
+        let e = check_attachment("long.flac", Some("audio/flac"), 30 * MB, MAX_BYTES).unwrap_err();
+        assert!(e.contains("30.0 MB"), "{e}");
+
+        // the limit counts in the same megabytes discord does
+        assert!(check_attachment("max.flac", Some("audio/flac"), MAX_BYTES, MAX_BYTES).is_ok());
+        let e = check_attachment("over.flac", Some("audio/flac"), MAX_BYTES + 1, MAX_BYTES)
+            .unwrap_err();
+        assert!(e.contains("25.0 MB, files up to 25 MB"), "{e}");
+    }
+}
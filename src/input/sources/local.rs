@@ -0,0 +1,232 @@
+use poise::serenity_prelude::async_trait;
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, Input};
+use std::{ffi::OsStr, path::PathBuf, time::Duration};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSource, MediaSourceStream},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+/// A lazily opened file of the music library, see [`Library`].
+///
+/// [`Library`]: crate::input::library::Library
+#[derive(Clone, Debug)]
+pub struct LocalFile {
+    path: PathBuf,
+    metadata: Option<AuxMetadata>,
+}
+
+impl LocalFile {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            metadata: None,
+        }
+    }
+
+    fn hint(&self) -> Hint {
+        let mut hint = Hint::new();
+        if let Some(ext) = self.path.extension().and_then(OsStr::to_str) {
+            hint.with_extension(ext);
+        }
+        hint
+    }
+
+    /// Checks the file is still there, it may have been moved since it got queued.
+    pub async fn resolve(&mut self) -> Result<(), AudioStreamError> {
+        tokio::fs::metadata(&self.path)
+            .await
+            .map(drop)
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))
+    }
+
+    // reads the embedded tags and the duration, falling back to the file name as title
+    fn probe_metadata(&self) -> Result<AuxMetadata, AudioStreamError> {
+        let file =
+            std::fs::File::open(&self.path).map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut probed = symphonia::default::get_probe()
+            .format(
+                &self.hint(),
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+        let mut meta = AuxMetadata {
+            title: self
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        // tags in front of the container, like id3 ones, come first and the container's own
+        // override them
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            apply_tags(&mut meta, revision);
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            apply_tags(&mut meta, revision);
+        }
+
+        if let Some(params) = probed
+            .format
+            .default_track()
+            .map(|track| &track.codec_params)
+        {
+            meta.sample_rate = params.sample_rate;
+            meta.channels = params.channels.map(|channels| channels.count() as u8);
+            if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+                let time = time_base.calc_time(frames);
+                meta.duration =
+                    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+            }
+        }
+
+        Ok(meta)
+    }
+}
+
+fn apply_tags(meta: &mut AuxMetadata, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        // riff info values keep the nul padding of their chunk
+        let value = tag.value.to_string();
+        let value = value.trim_end_matches('\0').trim();
+        if value.is_empty() {
+            continue;
+        }
+        let value = Some(value.to_string());
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => meta.title = value,
+            Some(StandardTagKey::Artist) => meta.artist = value,
+            Some(StandardTagKey::AlbumArtist) if meta.artist.is_none() => meta.artist = value,
+            Some(StandardTagKey::Album) => meta.album = value,
+            Some(StandardTagKey::TrackNumber) => meta.track = value,
+            Some(StandardTagKey::Date | StandardTagKey::ReleaseDate) => meta.date = value,
+            _ => {}
+        }
+    }
+}
+
+impl From<LocalFile> for Input {
+    fn from(val: LocalFile) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for LocalFile {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let file = tokio::fs::File::open(&self.path)
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+        Ok(AudioStream {
+            input: Box::new(file.into_std().await),
+            hint: Some(self.hint()),
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        if let Some(meta) = self.metadata.as_ref() {
+            return Ok(meta.clone());
+        }
+
+        // probing reads through the file, keep it off the runtime's threads
+        let src = self.clone();
+        let meta = tokio::task::spawn_blocking(move || src.probe_metadata())
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))??;
+
+        self.metadata = Some(meta.clone());
+        Ok(meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one second of 8khz mono silence along with a RIFF INFO chunk naming it
+    fn tagged_wav(path: &std::path::Path) {
+        let samples = vec![0u8; 8000 * 2];
+        let mut info = b"INFO".to_vec();
+        for (id, value) in [(b"INAM", "Local Song"), (b"IART", "Local Artist")] {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            if value.len() % 2 == 1 {
+                value.push(0);
+            }
+            info.extend_from_slice(id);
+            info.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            info.extend_from_slice(&value);
+        }
+
+        let mut chunks = vec![];
+        chunks.extend_from_slice(b"fmt ");
+        chunks.extend_from_slice(&16u32.to_le_bytes());
+        chunks.extend_from_slice(&1u16.to_le_bytes()); // pcm
+        chunks.extend_from_slice(&1u16.to_le_bytes()); // mono
+        chunks.extend_from_slice(&8000u32.to_le_bytes());
+        chunks.extend_from_slice(&16000u32.to_le_bytes());
+        chunks.extend_from_slice(&2u16.to_le_bytes());
+        chunks.extend_from_slice(&16u16.to_le_bytes());
+        chunks.extend_from_slice(b"LIST");
+        chunks.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(&info);
+        chunks.extend_from_slice(b"data");
+        chunks.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(&samples);
+
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(&chunks);
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_embedded_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("01 track.wav");
+        tagged_wav(&path);
+
+        let meta = LocalFile::new(path).aux_metadata().await.unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Local Song"));
+        assert_eq!(meta.artist.as_deref(), Some("Local Artist"));
+        assert_eq!(meta.duration, Some(Duration::from_secs(1)));
+        assert_eq!(meta.sample_rate, Some(8000));
+    }
+
+    #[tokio::test]
+    async fn untagged_files_are_named_after_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("01 track.wav");
+        tagged_wav(&path);
+        let mut wav = std::fs::read(&path).unwrap();
+        // drop the INFO chunk by turning it into one symphonia skips over
+        let list = wav.windows(4).position(|id| id == b"LIST").unwrap();
+        wav[list..list + 4].copy_from_slice(b"JUNK");
+        std::fs::write(&path, wav).unwrap();
+
+        let meta = LocalFile::new(path.clone()).aux_metadata().await.unwrap();
+        assert_eq!(meta.title.as_deref(), Some("01 track"));
+
+        let missing = LocalFile::new(dir.path().join("gone.wav")).resolve().await;
+        assert!(missing.is_err());
+    }
+}
@@ -1 +1,4 @@
+pub mod attachment;
+pub mod local;
 pub mod spotdl;
+pub mod ytdl;
@@ -1,17 +1,26 @@
-#[allow(dead_code)]
-use crate::input::metadata::spotdl::Output;
+use crate::input::{
+    audio_cache::{isrc_key, AudioCache},
+    cache::{expires_soon, ResolveCache},
+    metadata::spotdl::Output,
+    process::Tool,
+    sources::ytdl::YoutubeDl,
+    spotify::SpotifyApi,
+};
 use crate::models::metadata::spotdl::Song;
 use anyhow::Result;
 use core::option::Option;
 use poise::serenity_prelude::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
-    Client,
+    Client, StatusCode,
 };
 use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, HttpRequest, Input};
-use std::{error::Error, io::ErrorKind, sync::Arc};
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use symphonia_core::io::MediaSource;
-use tokio::process::Command;
 
 const SPOTIFY_DL_COMMAND: &str = "spotdl";
 
@@ -41,11 +50,35 @@ enum QueryType {
 /// [`HttpRequest`]: super::HttpRequest
 #[derive(Clone, Debug)]
 pub struct SpotifyDl {
-    program: &'static str,
+    spotdl: Tool,
+    // runs the youtube fallback, yt-dlp on `PATH` when not set
+    ytdlp: Option<Tool>,
     client: Client,
     metadata: Option<AuxMetadata>,
     query: QueryType,
     credentials: Option<SpotifyCredential>,
+    song: Option<Song>,
+    // the stream last resolved by spotdl, signed urls stop working once they expired
+    stream: Option<Output>,
+    api: Option<Arc<SpotifyApi>>,
+    cache: Option<Arc<ResolveCache>>,
+    audio_cache: Option<Arc<AudioCache>>,
+    fallback: Option<YoutubeFallback>,
+}
+
+/// The youtube search a [`SpotifyDl`] plays from once spotdl failed to resolve it.
+#[derive(Clone, Debug)]
+struct YoutubeFallback {
+    query: String,
+    src: YoutubeDl,
+}
+
+/// Where a [`SpotifyDl`] gets its audio from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolvePath {
+    Spotdl,
+    /// spotdl failed, the best youtube match for the contained search query is used instead
+    YoutubeSearch(String),
 }
 
 #[derive(Debug, Clone)]
@@ -69,51 +102,225 @@ impl SpotifyDl {
     /// [`new`]: Self::new
     #[must_use]
     fn new_spotdl_like(
-        program: &'static str,
+        program: &str,
         client: Client,
         url: String,
         credentials: Option<SpotifyCredential>,
     ) -> Self {
         Self {
-            program,
+            spotdl: Tool::new(program),
+            ytdlp: None,
             client,
             metadata: None,
             query: QueryType::UrlOrSearch(url),
             credentials,
+            song: None,
+            stream: None,
+            api: None,
+            cache: None,
+            audio_cache: None,
+            fallback: None,
         }
     }
 
-    async fn query(&mut self) -> Result<Vec<Output>, AudioStreamError> {
-        let query_str = match &self.query {
-            QueryType::UrlOrSearch(url) => url,
+    /// Creates a lazy request for a song whose metadata is already known, e.g. one entry of a
+    /// playlist returned by [`songs`], only the stream url is looked up once it gets played.
+    ///
+    /// [`songs`]: Self::songs
+    #[must_use]
+    pub fn from_song(client: Client, song: Song, credentials: Option<SpotifyCredential>) -> Self {
+        let mut src = Self::new(client, song.url.clone(), credentials);
+        src.metadata = Some(Output::from_song(&song, String::new()).as_aux_metadata());
+        src.song = Some(song);
+        src
+    }
+
+    /// Looks metadata up with the Spotify Web API instead of spotdl `save`, spotdl is then
+    /// only used to find the stream url.
+    #[must_use]
+    pub fn with_api(mut self, api: Arc<SpotifyApi>) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    /// Reuses what spotdl resolved before, for this or any other source sharing `cache`.
+    #[must_use]
+    pub fn with_cache(mut self, cache: Arc<ResolveCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Plays songs from `audio_cache` once they were streamed through it, nothing is cached
+    /// when it's `None`.
+    #[must_use]
+    pub fn with_audio_cache(mut self, audio_cache: Option<Arc<AudioCache>>) -> Self {
+        self.audio_cache = audio_cache;
+        self
+    }
+
+    /// Runs `spotdl` and, when falling back to youtube, `ytdlp` instead of the plain
+    /// programs on `PATH`.
+    #[must_use]
+    pub fn with_tools(mut self, spotdl: Tool, ytdlp: Tool) -> Self {
+        self.spotdl = spotdl;
+        self.ytdlp = Some(ytdlp);
+        self
+    }
+
+    /// Resolves every song behind the query, a track gives a single song while playlist and
+    /// album links give one song per entry.
+    ///
+    /// On failure the source falls back to a youtube search for the raw query, see
+    /// [`resolve_path`].
+    ///
+    /// [`resolve_path`]: Self::resolve_path
+    pub async fn songs(&mut self) -> Result<Vec<Song>, AudioStreamError> {
+        let QueryType::UrlOrSearch(query_str) = &self.query;
+        let songs = match self.lookup_songs(query_str).await {
+            Ok(songs) => songs,
+            Err(e) => {
+                self.fall_back(&e);
+                return Err(e);
+            }
         };
-        let url = self.process_url_command(query_str).await;
 
-        let meta = self.process_save_command(query_str).await;
+        self.metadata = Some(Output::from_song(&songs[0], String::new()).as_aux_metadata());
+        self.song = Some(songs[0].clone());
+
+        Ok(songs)
+    }
+
+    /// Resolves the stream ahead of playback, so creating the track later on only has to
+    /// connect to it. Needs a cache shared with the source that gets played.
+    ///
+    /// On failure the source falls back to a youtube search, which is resolved instead.
+    pub async fn resolve(&mut self) -> Result<(), AudioStreamError> {
+        if let Some(fallback) = self.fallback.as_mut() {
+            return fallback.src.resolve().await;
+        }
+        if let (Some(audio_cache), Some(key)) = (&self.audio_cache, self.audio_key()) {
+            if audio_cache.contains(&key) {
+                return Ok(());
+            }
+        }
+
+        match self.fresh_stream().await {
+            Ok(_) => Ok(()),
+            Err(e) => self.fall_back(&e).resolve().await,
+        }
+    }
+
+    /// Tells whether the audio comes from spotdl or from the youtube search it fell back to.
+    pub fn resolve_path(&self) -> ResolvePath {
+        match &self.fallback {
+            Some(fallback) => ResolvePath::YoutubeSearch(fallback.query.clone()),
+            None => ResolvePath::Spotdl,
+        }
+    }
+
+    // searches for "<artist> - <name>" when spotdl at least found the song, for the raw
+    // query otherwise
+    fn search_query(&self) -> String {
+        match &self.song {
+            Some(song) => format!("{} - {}", song.artist, song.name),
+            None => {
+                let QueryType::UrlOrSearch(query_str) = &self.query;
+                query_str.clone()
+            }
+        }
+    }
+
+    fn fall_back(&mut self, reason: &AudioStreamError) -> &mut YoutubeDl {
+        if self.fallback.is_none() {
+            let query = self.search_query();
+            println!(
+                "spotdl failed ({}), searching youtube for \"{}\"",
+                reason, query
+            );
+            let mut src = YoutubeDl::new_search(self.client.clone(), query.clone());
+            if let Some(ytdlp) = &self.ytdlp {
+                src = src.with_tool(ytdlp.clone());
+            }
+            if let Some(cache) = &self.cache {
+                src = src.with_cache(cache.clone());
+            }
+            src = src.with_audio_cache(self.audio_cache.clone());
+            self.fallback = Some(YoutubeFallback { query, src });
+        }
+
+        &mut self.fallback.as_mut().expect("fallback was just set").src
+    }
+
+    // gives up right away on queries spotdl failed lately, e.g. when resolving a copy of this
+    // source ahead of playback
+    async fn query(&mut self) -> Result<Vec<Output>, AudioStreamError> {
+        let QueryType::UrlOrSearch(query_str) = &self.query;
+        if let Some(reason) = self.cache.as_ref().and_then(|c| c.failure(query_str)) {
+            return Err(AudioStreamError::Fail(
+                format!("{} failed shortly before: {}", self.spotdl.program, reason).into(),
+            ));
+        }
+
+        let query_str = query_str.clone();
+        let result = self.query_spotdl().await;
+        if let (Err(e), Some(cache)) = (&result, &self.cache) {
+            cache.insert_failure(&query_str, &e.to_string());
+        }
+
+        result
+    }
+
+    async fn query_spotdl(&mut self) -> Result<Vec<Output>, AudioStreamError> {
+        let QueryType::UrlOrSearch(query_str) = &self.query;
+        let started = Instant::now();
+
+        let url = timed(self.process_url_command(query_str));
+        // songs coming from a playlist already carry their metadata, only the url is missing
+        let meta = timed(async {
+            match &self.song {
+                Some(song) => Ok(song.clone()),
+                None => self
+                    .lookup_songs(query_str)
+                    .await
+                    .map(|mut songs| songs.swap_remove(0)),
+            }
+        });
+
+        // both lookups run side by side and share the spotdl timeout, giving up on them drops
+        // their futures which kills the spawned processes
+        let Ok(((url, url_time), (meta, meta_time))) =
+            tokio::time::timeout(self.spotdl.timeout, async { tokio::join!(url, meta) }).await
+        else {
+            println!(
+                "resolving {} timed out after {:?}",
+                query_str, self.spotdl.timeout
+            );
+            return Err(AudioStreamError::Fail(
+                format!(
+                    "{} timed out after {:?}",
+                    self.spotdl.program, self.spotdl.timeout
+                )
+                .into(),
+            ));
+        };
+        println!(
+            "resolved {} in {:?} (url {:?}, metadata {:?})",
+            query_str,
+            started.elapsed(),
+            url_time,
+            meta_time
+        );
 
         match (url, meta) {
             (Ok(url), Ok(meta)) => {
                 println!("Both query and meta are Ok");
                 println!("query result: {}", url);
                 println!("meta result: {:?}", meta);
-                let out = Output {
-                    artist: Option::from(meta.artist),
-                    album: Option::from(meta.album_name),
-                    channel: None,
-                    duration: Option::from(meta.duration as f64),
-                    filesize: None,
-                    http_headers: None,
-                    release_date: Option::from(meta.date),
-                    thumbnail: Option::from(meta.cover_url),
-                    title: Option::from(meta.name),
-                    track: Option::from(meta.track_number.to_string()),
-                    upload_date: None,
-                    uploader: None,
-                    url: url.clone(),
-                    webpage_url: Some(url.clone()),
-                };
+                let out = Output::from_song(&meta, url);
 
                 self.metadata = Some(out.as_aux_metadata());
+                self.song = Some(meta);
+                self.stream = Some(out.clone());
 
                 Ok(vec![out])
             }
@@ -134,7 +341,83 @@ impl SpotifyDl {
         }
     }
 
-    async fn process_url_command(&self, query_str: &String) -> Result<String, AudioStreamError> {
+    // reuses the stream resolved along with the metadata unless its url is about to expire
+    async fn fresh_stream(&mut self) -> Result<Output, AudioStreamError> {
+        if let Some(stream) = self.stream.as_ref().filter(|s| !expires_soon(&s.url)) {
+            return Ok(stream.clone());
+        }
+
+        // panic safety: `query` should have ensured > 0 results if `Ok`
+        Ok(self.query().await?.swap_remove(0))
+    }
+
+    // songs are cached by their isrc, known once the metadata was looked up
+    fn audio_key(&self) -> Option<String> {
+        self.song.as_ref().and_then(|song| isrc_key(&song.isrc))
+    }
+
+    async fn cached_audio(&self) -> Option<AudioStream<Box<dyn MediaSource>>> {
+        self.audio_cache.as_ref()?.stream(&self.audio_key()?).await
+    }
+
+    // streams the song, teeing it into the audio cache along the way
+    async fn play(
+        &self,
+        stream: Output,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let stream = self.http_request(stream).create_async().await?;
+
+        Ok(match (&self.audio_cache, self.audio_key()) {
+            (Some(audio_cache), Some(key)) => audio_cache.tee(key, stream),
+            _ => stream,
+        })
+    }
+
+    fn http_request(&self, stream: Output) -> HttpRequest {
+        let mut headers = HeaderMap::default();
+
+        if let Some(map) = stream.http_headers {
+            headers.extend(map.iter().filter_map(|(k, v)| {
+                Some((
+                    HeaderName::from_bytes(k.as_bytes()).ok()?,
+                    HeaderValue::from_str(v).ok()?,
+                ))
+            }));
+        }
+
+        HttpRequest {
+            client: self.client.clone(),
+            request: stream.url,
+            headers,
+            content_length: stream.filesize,
+        }
+    }
+
+    async fn lookup_songs(&self, query_str: &str) -> Result<Vec<Song>, AudioStreamError> {
+        if let Some(songs) = self.cache.as_ref().and_then(|cache| cache.songs(query_str)) {
+            return Ok(songs);
+        }
+
+        let songs = match &self.api {
+            Some(api) => api.songs(query_str).await?,
+            None => self.process_save_command(query_str).await?,
+        };
+        if let Some(cache) = &self.cache {
+            cache.insert_songs(query_str, &songs);
+        }
+
+        Ok(songs)
+    }
+
+    async fn process_url_command(&self, query_str: &str) -> Result<String, AudioStreamError> {
+        if let Some(url) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.stream_url(query_str))
+        {
+            return Ok(url);
+        }
+
         let spotdl_url_args: Vec<&str> = match &self.credentials {
             Some(credentials) => vec![
                 SPOTIFY_DL_OPTION_URL,
@@ -146,58 +429,45 @@ impl SpotifyDl {
             ],
             None => vec![SPOTIFY_DL_OPTION_URL, query_str],
         };
-        let url_output = Command::new(self.program)
-            .args(spotdl_url_args)
-            .output()
-            .await
-            .map_err(|e| {
-                AudioStreamError::Fail(if e.kind() == ErrorKind::NotFound {
-                    format!("could not find executable '{}' on path", self.program).into()
-                } else {
-                    Box::new(e)
-                })
-            });
-
-        match url_output {
-            Ok(output) => {
-                if !output.status.success() {
-                    return Err(AudioStreamError::Fail(
-                        format!(
-                            "{} failed with non-zero status code: {}",
-                            self.program,
-                            std::str::from_utf8(&output.stderr[..]).unwrap_or("<no error message>")
-                        )
-                        .into(),
-                    ));
-                };
-
-                // NOTE: must be split_mut for spotdl result and skip the first two lines which are
-                // [Processing query: <query>] and [url: <url>]
-                // spotdl result is not json format, e.g
-                // # spotdl url "suger for the pill"
-                // Processing query: suger for the pill
-                // https://rr2---sn-cxaaj5o5q5-tt1ek.googlevideo.com/videoplayback?expire=173362572...
-                let url = String::from_utf8(output.stdout)
-                    .map_err(|e| AudioStreamError::Fail(Box::new(e)))?
-                    .trim()
-                    .split('\n')
-                    .last()
-                    .into_iter()
-                    .collect::<String>();
-
-                Ok(url)
-            }
-            Err(e) => Err(AudioStreamError::Fail(Box::new(e))),
+        let stdout = self.spotdl.run(&spotdl_url_args).await?;
+
+        // NOTE: must be split_mut for spotdl result and skip the first two lines which are
+        // [Processing query: <query>] and [url: <url>]
+        // spotdl result is not json format, e.g
+        // # spotdl url "suger for the pill"
+        // Processing query: suger for the pill
+        // https://rr2---sn-cxaaj5o5q5-tt1ek.googlevideo.com/videoplayback?expire=173362572...
+        let url = String::from_utf8(stdout)
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?
+            .trim()
+            .split('\n')
+            .next_back()
+            .into_iter()
+            .collect::<String>();
+        if let Some(cache) = &self.cache {
+            cache.insert_stream_url(query_str, &url);
         }
+
+        Ok(url)
     }
 
-    async fn process_save_command(&self, query_str: &String) -> Result<Song, AudioStreamError> {
+    async fn process_save_command(&self, query_str: &str) -> Result<Vec<Song>, AudioStreamError> {
+        // every invocation gets its own scratch directory so concurrent lookups never read
+        // each other's save file, the directory is removed once `save_dir` is dropped, which
+        // also covers the command failing or this future being cancelled
+        let save_dir = tempfile::Builder::new()
+            .prefix("spotdl-")
+            .tempdir()
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        let save_file = save_dir.path().join(SPOTIFY_DL_FILE_NAME);
+        let save_file = save_file.to_string_lossy();
+
         let spotdl_save_args: Vec<&str> = match &self.credentials {
             Some(credentials) => vec![
                 SPOTIFY_DL_OPTION_SAVE,
                 query_str,
                 SPOTIFY_DL_OPTION_SAVE_SAVE_FILE_FLAG,
-                SPOTIFY_DL_FILE_NAME,
+                &save_file,
                 SPOTIFY_DL_OPTION_SPOTIFY_CLIENT_ID_FLAG,
                 credentials.client_id.as_ref(),
                 SPOTIFY_DL_OPTION_SPOTIFY_CLIENT_SECRET_FLAG,
@@ -207,37 +477,44 @@ impl SpotifyDl {
                 SPOTIFY_DL_OPTION_SAVE,
                 query_str,
                 SPOTIFY_DL_OPTION_SAVE_SAVE_FILE_FLAG,
-                SPOTIFY_DL_FILE_NAME,
+                &save_file,
             ],
         };
 
-        match Command::new(self.program).args(spotdl_save_args).spawn() {
-            Ok(mut child) => match child.wait().await {
-                    Ok(status) => {
-                        if !status.success() {
-                            return Err(AudioStreamError::Fail(
-                                format!("{} failed with non-zero status code", self.program).into(),
-                            ));
-                        }
-                        // Process completed successfully, handle the result here
-                    match Song::from_file(SPOTIFY_DL_FILE_NAME).await {
-                            Ok(songs) => {
-                            if songs.is_empty() {
-                                    Err(AudioStreamError::Fail("No song found in the file".into()))
-                            } else {
-                                Ok(songs[0].clone())
-                                }
-                            }
-                            Err(e) => Err(AudioStreamError::Fail(e)),
-                        }
-                    }
-                Err(e) => Err(AudioStreamError::Fail(Box::new(e))),
-            },
-            Err(e) => Err(AudioStreamError::Fail(Box::new(e))),
+        self.spotdl.run(&spotdl_save_args).await?;
+
+        match Song::from_file(&save_file).await {
+            Ok(songs) => {
+                if songs.is_empty() {
+                    Err(AudioStreamError::Fail("No song found in the file".into()))
+                } else {
+                    Ok(songs)
+                }
+            }
+            Err(e) => Err(AudioStreamError::Fail(e)),
         }
     }
 }
 
+async fn timed<T>(future: impl std::future::Future<Output = T>) -> (T, Duration) {
+    let started = Instant::now();
+    let output = future.await;
+    (output, started.elapsed())
+}
+
+// `HttpRequest` turns error statuses into a plain message rather than a reqwest error, only
+// an error reqwest raised itself carries the status
+fn is_forbidden(e: &AudioStreamError) -> bool {
+    let AudioStreamError::Fail(e) = e else {
+        return false;
+    };
+
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.status() == Some(StatusCode::FORBIDDEN),
+        None => e.to_string() == format!("failed with http status code: {}", StatusCode::FORBIDDEN),
+    }
+}
+
 impl From<SpotifyDl> for Input {
     fn from(val: SpotifyDl) -> Self {
         Input::Lazy(Box::new(val))
@@ -253,39 +530,40 @@ impl Compose for SpotifyDl {
     async fn create_async(
         &mut self,
     ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
-        // panic safety: `query` should have ensured > 0 results if `Ok`
-        let mut results = self.query().await?;
-
-        match results.first() {
-            Some(ele) => {
-                println!("create_async result: {}", ele.url);
-            }
-            None => {
-                println!("create_async no result");
-            }
+        if let Some(fallback) = self.fallback.as_mut() {
+            return fallback.src.create_async().await;
         }
-
-        let result = results.swap_remove(0);
-
-        let mut headers = HeaderMap::default();
-
-        if let Some(map) = result.http_headers {
-            headers.extend(map.iter().filter_map(|(k, v)| {
-                Some((
-                    HeaderName::from_bytes(k.as_bytes()).ok()?,
-                    HeaderValue::from_str(v).ok()?,
-                ))
-            }));
+        if let Some(stream) = self.cached_audio().await {
+            return Ok(stream);
         }
 
-        let mut req = HttpRequest {
-            client: self.client.clone(),
-            request: result.url,
-            headers,
-            content_length: result.filesize,
+        let stream = match self.fresh_stream().await {
+            Ok(stream) => stream,
+            Err(e) => return self.fall_back(&e).create_async().await,
         };
+        println!("create_async result: {}", stream.url);
+        // a song only resolved just now may be cached already
+        if let Some(stream) = self.cached_audio().await {
+            return Ok(stream);
+        }
 
-        req.create_async().await
+        match self.play(stream).await {
+            // the url can be revoked before it expires, resolve it once more
+            Err(e) if is_forbidden(&e) => {
+                println!("stream url was rejected, resolving it again");
+                self.stream = None;
+                if let Some(cache) = &self.cache {
+                    let QueryType::UrlOrSearch(query_str) = &self.query;
+                    cache.remove_stream_url(query_str);
+                }
+                let stream = match self.fresh_stream().await {
+                    Ok(stream) => stream,
+                    Err(e) => return self.fall_back(&e).create_async().await,
+                };
+                self.play(stream).await
+            }
+            result => result,
+        }
     }
 
     fn should_create_async(&self) -> bool {
@@ -296,8 +574,13 @@ impl Compose for SpotifyDl {
         if let Some(meta) = self.metadata.as_ref() {
             return Ok(meta.clone());
         }
+        if let Some(fallback) = self.fallback.as_mut() {
+            return fallback.src.aux_metadata().await;
+        }
 
-        self.query().await?;
+        if let Err(e) = self.query().await {
+            return self.fall_back(&e).aux_metadata().await;
+        }
 
         self.metadata.clone().ok_or_else(|| {
             let msg: Box<dyn Error + Send + Sync + 'static> =
@@ -307,16 +590,354 @@ impl Compose for SpotifyDl {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//
-//     #[tokio::test]
-//     #[ntest::timeout(20_000)]
-//     async fn ytdl_track_plays() {
-//         let songs = Song::from_file(SPOTIFY_DL_FILE_NAME).expect("spotdl save result is not json");
-//
-//         track_plays_mixed(|| SpotifyDl::new(Client::new(), YTDL_TARGET.into())).await;
-//     }
-//
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        os::unix::fs::PermissionsExt,
+        path::Path,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+    use tokio::task::JoinSet;
+
+    const SONG_TEMPLATE: &str = r#"[{"name": "__QUERY__", "artists": ["Fake Artist"], "artist": "Fake Artist",
+        "genres": [], "disc_number": 1, "disc_count": 1, "album_name": "Album of __QUERY__",
+        "album_artist": "Fake Artist", "album_type": "album", "duration": 180, "year": 2024,
+        "date": "2024-01-01", "track_number": 1, "tracks_count": 1, "song_id": "__QUERY__",
+        "explicit": false, "publisher": "", "url": "https://open.spotify.com/track/__QUERY__",
+        "isrc": "", "cover_url": "", "copyright_text": "", "popularity": 0, "album_id": "",
+        "artist_id": ""}]"#;
+
+    // writes a fake spotdl which answers `url` and `save` like the real one, logging every
+    // url lookup to `url.log` and every save file it was asked to write to `save.log`. It fails
+    // `save` for queries named "fail", fails `url` for ones named "nourl" and hangs on queries
+    // named "slow", after writing its pid to `spotdl.pid`. On "lag" each command waits for the
+    // other one to start, failing when they run one after the other
+    fn fake_spotdl(dir: &Path) -> String {
+        let template = dir.join("song.json");
+        std::fs::write(&template, SONG_TEMPLATE).unwrap();
+
+        let script = dir.join("spotdl");
+        std::fs::write(
+            &script,
+            format!(
+                r#"#!/bin/sh
+wait_for() {{
+    touch "{dir}/$1.started"
+    i=0
+    while [ ! -e "{dir}/$2.started" ]; do
+        i=$((i + 1))
+        [ $i -gt 50 ] && exit 1
+        sleep 0.1
+    done
+}}
+case "$1" in
+    url)
+        echo "$2" >> "{url_log}"
+        [ "$2" = "lag" ] && wait_for url save
+        [ "$2" = "nourl" ] && exit 1
+        echo "Processing query: $2"
+        echo "https://example.invalid/$2"
+        ;;
+    save)
+        echo "$4" >> "{log}"
+        sleep 0.2
+        [ "$2" = "slow" ] && echo $$ > "{pid}" && sleep 5
+        [ "$2" = "lag" ] && wait_for save url
+        sed "s/__QUERY__/$2/g" "{template}" > "$4"
+        [ "$2" = "fail" ] && exit 1
+        exit 0
+        ;;
+esac
+"#,
+                dir = dir.display(),
+                log = dir.join("save.log").display(),
+                url_log = dir.join("url.log").display(),
+                pid = dir.join("spotdl.pid").display(),
+                template = template.display(),
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        script.to_string_lossy().into_owned()
+    }
+
+    fn saved_files(dir: &Path) -> Vec<String> {
+        std::fs::read_to_string(dir.join("save.log"))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    fn scratch_dir_exists(save_file: &str) -> bool {
+        Path::new(save_file).parent().is_some_and(Path::exists)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_resolves_get_their_own_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        let mut resolves = JoinSet::new();
+        for i in 0..16 {
+            let query = format!("track-{i}");
+            let program = program.clone();
+            resolves.spawn(async move {
+                let mut src =
+                    SpotifyDl::new_spotdl_like(&program, Client::new(), query.clone(), None);
+                (query, src.aux_metadata().await)
+            });
+        }
+
+        while let Some(resolved) = resolves.join_next().await {
+            let (query, meta) = resolved.unwrap();
+            let meta = meta.expect("fake spotdl should resolve");
+            assert_eq!(meta.title.as_deref(), Some(query.as_str()));
+            assert_eq!(meta.album, Some(format!("Album of {query}")));
+        }
+
+        let saved = saved_files(dir.path());
+        assert_eq!(saved.len(), 16);
+        assert_eq!(
+            saved.iter().collect::<std::collections::HashSet<_>>().len(),
+            16,
+            "every resolve should use its own save file"
+        );
+        assert!(saved.iter().all(|file| !scratch_dir_exists(file)));
+    }
+
+    #[tokio::test]
+    async fn save_file_is_removed_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "fail".into(), None);
+        assert!(src.songs().await.is_err());
+
+        let saved = saved_files(dir.path());
+        assert_eq!(saved.len(), 1);
+        assert!(!scratch_dir_exists(&saved[0]));
+    }
+
+    #[tokio::test]
+    async fn save_file_is_removed_on_cancellation() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "slow".into(), None);
+        let resolve = tokio::time::timeout(Duration::from_millis(500), src.aux_metadata()).await;
+        assert!(resolve.is_err(), "resolve should still be pending");
+
+        let saved = saved_files(dir.path());
+        assert_eq!(saved.len(), 1);
+        assert!(!scratch_dir_exists(&saved[0]));
+    }
+
+    #[tokio::test]
+    async fn failed_resolves_fall_back_to_youtube_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "track-1".into(), None);
+        src.songs().await.unwrap();
+        assert_eq!(src.resolve_path(), ResolvePath::Spotdl);
+        src.fall_back(&AudioStreamError::Unsupported);
+        assert_eq!(
+            src.resolve_path(),
+            ResolvePath::YoutubeSearch("Fake Artist - track-1".into())
+        );
+
+        // without metadata there is nothing better to search for than the query itself
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "fail".into(), None);
+        assert!(src.songs().await.is_err());
+        assert_eq!(
+            src.resolve_path(),
+            ResolvePath::YoutubeSearch("fail".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_url_lookups_fall_back_to_youtube_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+        // there is no yt-dlp, only the way the source went matters here
+        let ytdlp = Tool::new(dir.path().join("yt-dlp").to_string_lossy());
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "nourl".into(), None)
+            .with_tools(Tool::new(&program), ytdlp);
+        src.songs().await.unwrap();
+        assert_eq!(src.resolve_path(), ResolvePath::Spotdl);
+
+        let e = src.resolve().await.unwrap_err();
+        assert!(e.to_string().contains("yt-dlp"), "{e}");
+        assert_eq!(
+            src.resolve_path(),
+            ResolvePath::YoutubeSearch("Fake Artist - nourl".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn resolving_a_copy_spares_the_queued_source_from_spotdl() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+        let ytdlp = Tool::new(dir.path().join("yt-dlp").to_string_lossy());
+        let cache = Arc::new(ResolveCache::default());
+        let url_lookups = || {
+            std::fs::read_to_string(dir.path().join("url.log"))
+                .unwrap_or_default()
+                .lines()
+                .count()
+        };
+
+        for (query, resolves) in [("track", true), ("nourl", false)] {
+            let mut queued =
+                SpotifyDl::new_spotdl_like(&program, Client::new(), query.into(), None)
+                    .with_tools(Tool::new(&program), ytdlp.clone())
+                    .with_cache(cache.clone());
+            queued.songs().await.unwrap();
+            let lookups = url_lookups();
+
+            // prefetching resolves a copy, the queued source only shares the cache with it
+            assert_eq!(queued.clone().resolve().await.is_ok(), resolves, "{query}");
+            assert_eq!(url_lookups(), lookups + 1, "{query}");
+            assert_eq!(queued.resolve().await.is_ok(), resolves, "{query}");
+            assert_eq!(url_lookups(), lookups + 1, "{query}");
+
+            let path = match resolves {
+                true => ResolvePath::Spotdl,
+                false => ResolvePath::YoutubeSearch(format!("Fake Artist - {query}")),
+            };
+            assert_eq!(queued.resolve_path(), path);
+        }
+    }
+
+    #[tokio::test]
+    async fn expiring_stream_urls_are_resolved_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let stream_url = |expire: Duration| {
+            format!(
+                "https://rr1---sn-abc.googlevideo.com/videoplayback?expire={}&ei=xyz",
+                expire.as_secs()
+            )
+        };
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "track-1".into(), None);
+        src.songs().await.unwrap();
+
+        let fresh = stream_url(now + Duration::from_secs(6 * 60 * 60));
+        src.stream = Some(Output::from_song(src.song.as_ref().unwrap(), fresh.clone()));
+        assert_eq!(src.fresh_stream().await.unwrap().url, fresh);
+
+        let stale = stream_url(now + Duration::from_secs(60));
+        src.stream = Some(Output::from_song(src.song.as_ref().unwrap(), stale));
+        assert_eq!(
+            src.fresh_stream().await.unwrap().url,
+            "https://example.invalid/track-1"
+        );
+
+        assert!(!expires_soon("https://example.invalid/track-1"));
+    }
+
+    // answers every request with `status` and an empty body
+    async fn http_server(status: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let _ = socket.read(&mut [0; 4096]).await;
+                let response =
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn rejected_stream_urls_are_recognized() {
+        for (status, forbidden) in [
+            ("403 Forbidden", true),
+            ("404 Not Found", false),
+            ("500 Internal Server Error", false),
+        ] {
+            let url = http_server(status).await;
+            let e = HttpRequest::new(Client::new(), url)
+                .create_async()
+                .await
+                .map(drop)
+                .unwrap_err();
+            assert_eq!(is_forbidden(&e), forbidden, "{status}: {e}");
+        }
+
+        let e = AudioStreamError::Fail("spotdl failed with exit status: 403 Forbidden".into());
+        assert!(!is_forbidden(&e));
+        assert!(!is_forbidden(&AudioStreamError::Unsupported));
+    }
+
+    #[tokio::test]
+    async fn url_and_metadata_are_resolved_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        // the fake fails either lookup unless the other one runs at the same time
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "lag".into(), None);
+        let out = src.query().await.unwrap().swap_remove(0);
+
+        assert_eq!(out.url, "https://example.invalid/lag");
+        assert_eq!(out.title.as_deref(), Some("lag"));
+    }
+
+    #[tokio::test]
+    async fn timed_out_resolves_kill_spotdl() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+
+        let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "slow".into(), None);
+        src.spotdl.timeout = Duration::from_millis(500);
+        assert!(src.query().await.is_err());
+
+        let saved = saved_files(dir.path());
+        assert_eq!(saved.len(), 1);
+        assert!(!scratch_dir_exists(&saved[0]));
+
+        let pid = std::fs::read_to_string(dir.path().join("spotdl.pid")).unwrap();
+        let pid: i32 = pid.trim().parse().unwrap();
+        // a killed process may linger as a zombie until it's reaped
+        for _ in 0..50 {
+            // safety: signal 0 only checks whether the process exists
+            let alive = unsafe { libc::kill(pid, 0) } == 0;
+            let zombie = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                .is_ok_and(|stat| stat.contains(") Z"));
+            if !alive || zombie {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("spotdl {pid} is still running");
+    }
+
+    #[tokio::test]
+    async fn cached_resolves_do_not_run_spotdl_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_spotdl(dir.path());
+        let cache = Arc::new(ResolveCache::default());
+
+        for _ in 0..2 {
+            let mut src = SpotifyDl::new_spotdl_like(&program, Client::new(), "track".into(), None)
+                .with_cache(cache.clone());
+            let out = src.query().await.unwrap().swap_remove(0);
+            assert_eq!(out.url, "https://example.invalid/track");
+            assert_eq!(out.title.as_deref(), Some("track"));
+        }
+
+        assert_eq!(saved_files(dir.path()).len(), 1);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::input::{
+    audio_cache::{video_key, AudioCache},
+    cache::ResolveCache,
+    metadata::spotdl::Output,
+    process::Tool,
+};
+use poise::serenity_prelude::async_trait;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client,
+};
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, HttpRequest, Input};
+use std::{error::Error, sync::Arc};
+use symphonia_core::io::MediaSource;
+
+const YOUTUBE_DL_COMMAND: &str = "yt-dlp";
+
+#[derive(Clone, Debug)]
+enum QueryType {
+    Url(String),
+    Search(String),
+}
+
+/// A lazily instantiated call to download a file, finding its URL via youtube-dl.
+///
+/// Works like songbird's `YoutubeDl`, except that yt-dlp runs as a [`Tool`] so a hanging
+/// lookup gets killed instead of blocking the command forever.
+#[derive(Clone, Debug)]
+pub struct YoutubeDl {
+    tool: Tool,
+    client: Client,
+    metadata: Option<AuxMetadata>,
+    query: QueryType,
+    cache: Option<Arc<ResolveCache>>,
+    audio_cache: Option<Arc<AudioCache>>,
+}
+
+impl YoutubeDl {
+    /// Creates a lazy request to select an audio stream from `url`, using "yt-dlp".
+    ///
+    /// This requires a reqwest client: ideally, one should be created and shared between
+    /// all requests.
+    #[must_use]
+    pub fn new(client: Client, url: String) -> Self {
+        Self::new_ytdl_like(YOUTUBE_DL_COMMAND, client, url)
+    }
+
+    /// Creates a lazy request to select an audio stream from `url` as in [`new`], using `program`.
+    ///
+    /// [`new`]: Self::new
+    #[must_use]
+    fn new_ytdl_like(program: &str, client: Client, url: String) -> Self {
+        Self {
+            tool: Tool::new(program),
+            client,
+            metadata: None,
+            query: QueryType::Url(url),
+            cache: None,
+            audio_cache: None,
+        }
+    }
+
+    /// Creates a request to search youtube for videos matching `query`, using "yt-dlp".
+    #[must_use]
+    pub fn new_search(client: Client, query: String) -> Self {
+        Self {
+            query: QueryType::Search(query),
+            ..Self::new(client, String::new())
+        }
+    }
+
+    /// Runs `tool` instead of the plain "yt-dlp" on `PATH`.
+    #[must_use]
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.tool = tool;
+        self
+    }
+
+    /// Reuses what yt-dlp answered before, for this or any other source sharing `cache`.
+    #[must_use]
+    pub fn with_cache(mut self, cache: Arc<ResolveCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Plays videos from `audio_cache` once they were streamed through it, nothing is cached
+    /// when it's `None`.
+    #[must_use]
+    pub fn with_audio_cache(mut self, audio_cache: Option<Arc<AudioCache>>) -> Self {
+        self.audio_cache = audio_cache;
+        self
+    }
+
+    /// Runs a search for the given query, returning a list of up to `n_results`
+    /// possible matches which are `AuxMetadata` objects containing a valid URL.
+    ///
+    /// Returns up to 5 matches by default.
+    pub async fn search(
+        &mut self,
+        n_results: Option<usize>,
+    ) -> Result<Vec<AuxMetadata>, AudioStreamError> {
+        let n_results = n_results.unwrap_or(5);
+
+        Ok(match &self.query {
+            // Safer to just return the metadata for the pointee if possible
+            QueryType::Url(_) => vec![self.aux_metadata().await?],
+            QueryType::Search(_) => self
+                .query(n_results)
+                .await?
+                .into_iter()
+                .map(|v| v.as_aux_metadata())
+                .collect(),
+        })
+    }
+
+    /// Resolves the stream ahead of playback, so creating the track later on only has to
+    /// connect to it. Needs a cache shared with the source that gets played.
+    pub async fn resolve(&mut self) -> Result<(), AudioStreamError> {
+        let key = video_key(self.query_str());
+        if let (Some(audio_cache), Some(key)) = (&self.audio_cache, key) {
+            if audio_cache.contains(&key) {
+                return Ok(());
+            }
+        }
+
+        self.stream_output().await.map(drop)
+    }
+
+    // the first result along with its stream url, a cached stream url is only trusted when it
+    // tells how long it stays valid
+    async fn stream_output(&mut self) -> Result<Output, AudioStreamError> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.playable_outputs(self.query_str()));
+        let mut results = match cached {
+            Some(out) => out,
+            None => self.run_query(1).await?,
+        };
+
+        // panic safety: `query` should have ensured > 0 results if `Ok`
+        Ok(results.swap_remove(0))
+    }
+
+    async fn cached_audio(&self, key: Option<&str>) -> Option<AudioStream<Box<dyn MediaSource>>> {
+        self.audio_cache.as_ref()?.stream(key?).await
+    }
+
+    fn query_str(&self) -> &str {
+        match &self.query {
+            QueryType::Url(url) => url,
+            QueryType::Search(query) => query,
+        }
+    }
+
+    // answers from the cache when possible, the stream urls of cached results may have
+    // expired though
+    async fn query(&mut self, n_results: usize) -> Result<Vec<Output>, AudioStreamError> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.outputs(self.query_str(), n_results));
+        match cached {
+            Some(out) if !out.is_empty() => {
+                self.metadata = Some(out[0].as_aux_metadata());
+                Ok(out)
+            }
+            _ => self.run_query(n_results).await,
+        }
+    }
+
+    async fn run_query(&mut self, n_results: usize) -> Result<Vec<Output>, AudioStreamError> {
+        let new_query;
+        let query_str = match &self.query {
+            QueryType::Url(url) => url,
+            QueryType::Search(query) => {
+                new_query = format!("ytsearch{n_results}:{query}");
+                &new_query
+            }
+        };
+        let ytdl_args = [
+            "-j",
+            query_str,
+            "-f",
+            "ba[abr>0][vcodec=none]/best",
+            "--no-playlist",
+        ];
+
+        let stdout = self.tool.run(&ytdl_args).await?;
+
+        let out = stdout
+            .split(|&b| b == b'\n')
+            .filter(|x| !x.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<Vec<Output>, _>>()
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+        let meta = out
+            .first()
+            .ok_or_else(|| {
+                AudioStreamError::Fail(format!("no results found for '{query_str}'").into())
+            })?
+            .as_aux_metadata();
+
+        self.metadata = Some(meta);
+        if let Some(cache) = &self.cache {
+            cache.insert_outputs(self.query_str(), n_results, &out);
+        }
+
+        Ok(out)
+    }
+}
+
+impl From<YoutubeDl> for Input {
+    fn from(val: YoutubeDl) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for YoutubeDl {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        // links tell the video before yt-dlp even runs
+        let key = video_key(self.query_str());
+        if let Some(stream) = self.cached_audio(key.as_deref()).await {
+            return Ok(stream);
+        }
+
+        let result = self.stream_output().await?;
+
+        // searches only tell which video they found now
+        let key = key.or_else(|| result.webpage_url.as_deref().and_then(video_key));
+        if let Some(stream) = self.cached_audio(key.as_deref()).await {
+            return Ok(stream);
+        }
+
+        let mut headers = HeaderMap::default();
+
+        if let Some(map) = result.http_headers {
+            headers.extend(map.iter().filter_map(|(k, v)| {
+                Some((
+                    HeaderName::from_bytes(k.as_bytes()).ok()?,
+                    HeaderValue::from_str(v).ok()?,
+                ))
+            }));
+        }
+
+        let mut req = HttpRequest {
+            client: self.client.clone(),
+            request: result.url,
+            headers,
+            content_length: result.filesize,
+        };
+
+        let stream = req.create_async().await?;
+
+        Ok(match (&self.audio_cache, key) {
+            (Some(audio_cache), Some(key)) => audio_cache.tee(key, stream),
+            _ => stream,
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        if let Some(meta) = self.metadata.as_ref() {
+            return Ok(meta.clone());
+        }
+
+        self.query(1).await?;
+
+        self.metadata.clone().ok_or_else(|| {
+            let msg: Box<dyn Error + Send + Sync + 'static> =
+                "Failed to instansiate any metadata... Should be unreachable.".into();
+            AudioStreamError::Fail(msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::fs::PermissionsExt, path::Path, time::Duration};
+
+    // answers like yt-dlp with one json line per result, hanging on "slow" and crashing
+    // on "crash", every run is logged to `calls.log`
+    fn fake_ytdl(dir: &Path) -> String {
+        let script = dir.join("yt-dlp");
+        std::fs::write(
+            &script,
+            r#"#!/bin/sh
+echo "$2" >> "$(dirname "$0")/calls.log"
+case "$2" in
+    *slow) sleep 30 ;;
+    *crash) echo "ERROR: [youtube] crash: Video unavailable" >&2; exit 1 ;;
+esac
+echo "{\"title\": \"$2\", \"url\": \"https://example.invalid/stream?expire=4102444800\", \"webpage_url\": \"https://youtu.be/abc\"}"
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        script.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn resolves_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_ytdl(dir.path());
+
+        let mut src =
+            YoutubeDl::new_ytdl_like(&program, Client::new(), "https://youtu.be/abc".into());
+        let meta = src.aux_metadata().await.unwrap();
+        assert_eq!(meta.title.as_deref(), Some("https://youtu.be/abc"));
+        assert_eq!(meta.source_url.as_deref(), Some("https://youtu.be/abc"));
+    }
+
+    #[tokio::test]
+    async fn hanging_and_crashing_lookups_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_ytdl(dir.path());
+
+        let mut src = YoutubeDl::new_ytdl_like(&program, Client::new(), "slow".into());
+        src.tool.timeout = Duration::from_millis(300);
+        let e = src.aux_metadata().await.unwrap_err();
+        assert!(e.to_string().contains("timed out"), "{e}");
+
+        let mut src = YoutubeDl::new_ytdl_like(&program, Client::new(), "crash".into());
+        let e = src.aux_metadata().await.unwrap_err();
+        assert!(e.to_string().contains("Video unavailable"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn cached_videos_play_without_ytdlp() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio_cache = Arc::new(AudioCache::open(dir.path(), 1 << 20).unwrap());
+        let stream = AudioStream {
+            input: Box::new(std::io::Cursor::new(vec![7u8; 1000])) as Box<dyn MediaSource>,
+            hint: None,
+        };
+        let mut input = audio_cache.tee("youtube-abc".into(), stream).input;
+        std::io::Read::read_to_end(&mut input, &mut vec![]).unwrap();
+        // the finished stream is stored in the background
+        while !audio_cache.contains("youtube-abc") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut src = YoutubeDl::new_ytdl_like(
+            "/nonexistent/yt-dlp",
+            Client::new(),
+            "https://www.youtube.com/watch?v=abc".into(),
+        )
+        .with_audio_cache(Some(audio_cache));
+        let stream = src.create_async().await.unwrap();
+        assert_eq!(stream.input.byte_len(), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn resolving_ahead_fills_the_shared_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = fake_ytdl(dir.path());
+        let cache = Arc::new(ResolveCache::default());
+
+        let src = YoutubeDl::new_ytdl_like(&program, Client::new(), "https://youtu.be/abc".into())
+            .with_cache(cache);
+        src.clone().resolve().await.unwrap();
+
+        let out = src.clone().stream_output().await.unwrap();
+        assert_eq!(out.url, "https://example.invalid/stream?expire=4102444800");
+        let calls = std::fs::read_to_string(dir.path().join("calls.log")).unwrap();
+        assert_eq!(calls.lines().count(), 1);
+    }
+}
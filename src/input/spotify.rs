@@ -0,0 +1,665 @@
+use crate::input::sources::spotdl::SpotifyCredential;
+use crate::models::metadata::{
+    spotdl::Song,
+    spotify::{Album, Page, Playlist, PlaylistItem, SearchResult, SongList, Token, Track},
+};
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use songbird::input::AudioStreamError;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const SPOTIFY_ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+const SPOTIFY_API_URL: &str = "https://api.spotify.com/v1";
+
+// tokens are fetched again a bit before they expire, so one never runs out mid-request
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// A Spotify Web API client looking up songs with the client credentials flow, in the same
+/// shape spotdl saves them in.
+///
+/// The access token is shared by all lookups and only fetched again once it expired or got
+/// rejected, so one client should be created and shared.
+#[derive(Debug)]
+pub struct SpotifyApi {
+    client: Client,
+    credentials: SpotifyCredential,
+    accounts_url: String,
+    api_url: String,
+    token: Mutex<Option<CachedToken>>,
+    // playlists and albums are only paged through up to this many tracks
+    max_tracks: usize,
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    value: String,
+    expires_at: Instant,
+}
+
+/// What a Spotify link points to.
+#[derive(Debug, PartialEq, Eq)]
+enum Link<'a> {
+    Track(&'a str),
+    Album(&'a str),
+    Playlist(&'a str),
+}
+
+impl<'a> Link<'a> {
+    // understands open.spotify.com links, with or without a locale, and spotify: uris
+    fn parse(query: &'a str) -> Option<Self> {
+        let query = query.trim();
+        let parts: Vec<&str> = match query.strip_prefix("spotify:") {
+            Some(uri) => uri.split(':').collect(),
+            None => {
+                let rest = query
+                    .strip_prefix("https://")
+                    .or_else(|| query.strip_prefix("http://"))?;
+                let path = rest.strip_prefix("open.spotify.com/")?;
+                let path = path.split(['?', '#']).next()?;
+                path.split('/')
+                    .filter(|part| !part.is_empty() && !part.starts_with("intl-"))
+                    .collect()
+            }
+        };
+
+        match parts[..] {
+            ["track", id] => Some(Link::Track(id)),
+            ["album", id] => Some(Link::Album(id)),
+            ["playlist", id] => Some(Link::Playlist(id)),
+            _ => None,
+        }
+    }
+}
+
+/// The `spotify:<kind>:<id>` uri a Spotify link points to, whichever form the link came in.
+pub fn canonical_uri(query: &str) -> Option<String> {
+    Link::parse(query).map(|link| match link {
+        Link::Track(id) => format!("spotify:track:{}", id),
+        Link::Album(id) => format!("spotify:album:{}", id),
+        Link::Playlist(id) => format!("spotify:playlist:{}", id),
+    })
+}
+
+fn fail(e: impl std::error::Error + Send + Sync + 'static) -> AudioStreamError {
+    AudioStreamError::Fail(Box::new(e))
+}
+
+impl SpotifyApi {
+    pub fn new(client: Client, credentials: SpotifyCredential) -> Self {
+        Self::with_urls(client, credentials, SPOTIFY_ACCOUNTS_URL, SPOTIFY_API_URL)
+    }
+
+    fn with_urls(
+        client: Client,
+        credentials: SpotifyCredential,
+        accounts_url: &str,
+        api_url: &str,
+    ) -> Self {
+        Self {
+            client,
+            credentials,
+            accounts_url: accounts_url.to_string(),
+            api_url: api_url.to_string(),
+            token: Mutex::new(None),
+            max_tracks: usize::MAX,
+        }
+    }
+
+    /// Stops looking up playlists and albums once `max_tracks` of their tracks are known,
+    /// they are cut off there.
+    #[must_use]
+    pub fn with_max_tracks(mut self, max_tracks: usize) -> Self {
+        self.max_tracks = max_tracks;
+        self
+    }
+
+    /// Looks up every song behind `query`, a track link gives a single song while playlist
+    /// and album links give one song per entry, up to the cap set with [`with_max_tracks`].
+    /// Anything else is searched for, giving the best match.
+    ///
+    /// [`with_max_tracks`]: Self::with_max_tracks
+    pub async fn songs(&self, query: &str) -> Result<Vec<Song>, AudioStreamError> {
+        let songs = match Link::parse(query) {
+            Some(Link::Track(id)) => {
+                let track: Track = self.get(&format!("{}/tracks/{}", self.api_url, id)).await?;
+                vec![track.into_song(None, None)]
+            }
+            Some(Link::Album(id)) => self.album(id).await?,
+            Some(Link::Playlist(id)) => self.playlist(id).await?,
+            None => self.search(query).await?,
+        };
+
+        if songs.is_empty() {
+            return Err(AudioStreamError::Fail("No song found".into()));
+        }
+
+        Ok(songs)
+    }
+
+    async fn album(&self, id: &str) -> Result<Vec<Song>, AudioStreamError> {
+        let mut album: Album = self.get(&format!("{}/albums/{}", self.api_url, id)).await?;
+
+        let mut tracks = std::mem::take(&mut album.tracks.items);
+        let mut next = album.tracks.next.take();
+        while tracks.len() < self.max_tracks {
+            let Some(url) = next.take() else {
+                break;
+            };
+            let page: Page<Track> = self.get(&url).await?;
+            tracks.extend(page.items);
+            next = page.next;
+        }
+        tracks.truncate(self.max_tracks);
+
+        let list = SongList {
+            name: &album.name,
+            url: &album.external_urls.spotify,
+            length: album.total_tracks.max(tracks.len() as i32),
+        };
+        Ok(tracks
+            .into_iter()
+            .enumerate()
+            .map(|(i, track)| track.into_song(Some(&album), Some((&list, i as i32 + 1))))
+            .collect())
+    }
+
+    async fn playlist(&self, id: &str) -> Result<Vec<Song>, AudioStreamError> {
+        let mut playlist: Playlist = self
+            .get(&format!("{}/playlists/{}", self.api_url, id))
+            .await?;
+
+        // removed tracks, local files and podcast episodes can't be looked up
+        let playable = |items: Vec<PlaylistItem>| {
+            items
+                .into_iter()
+                .filter_map(|item| item.track)
+                .filter(|track| track.kind == "track" && track.id.is_some())
+        };
+
+        let mut tracks: Vec<Track> = playable(std::mem::take(&mut playlist.tracks.items)).collect();
+        let mut next = playlist.tracks.next.take();
+        while tracks.len() < self.max_tracks {
+            let Some(url) = next.take() else {
+                break;
+            };
+            let page: Page<PlaylistItem> = self.get(&url).await?;
+            tracks.extend(playable(page.items));
+            next = page.next;
+        }
+
+        // the total counts unplayable entries too, it's only known exactly when paged through
+        let length = match next {
+            Some(_) => playlist.tracks.total,
+            None => tracks.len() as i32,
+        };
+        tracks.truncate(self.max_tracks);
+
+        let list = SongList {
+            name: &playlist.name,
+            url: &playlist.external_urls.spotify,
+            length: length.max(tracks.len() as i32),
+        };
+        Ok(tracks
+            .into_iter()
+            .enumerate()
+            .map(|(i, track)| track.into_song(None, Some((&list, i as i32 + 1))))
+            .collect())
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Song>, AudioStreamError> {
+        let url = format!("{}/search", self.api_url);
+        let result: SearchResult = self
+            .request(&url, &[("q", query), ("type", "track"), ("limit", "1")])
+            .await?;
+
+        Ok(result
+            .tracks
+            .items
+            .into_iter()
+            .map(|track| track.into_song(None, None))
+            .collect())
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, AudioStreamError> {
+        self.request(url, &[]).await
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, AudioStreamError> {
+        let mut rejected = false;
+        loop {
+            let token = self.token().await?;
+            let response = self
+                .client
+                .get(url)
+                .query(query)
+                .bearer_auth(&token)
+                .send()
+                .await
+                .map_err(fail)?;
+
+            match response.status() {
+                // the token got revoked before it expired, try once more with a new one
+                StatusCode::UNAUTHORIZED if !rejected => {
+                    rejected = true;
+                    self.forget_token(&token).await;
+                }
+                StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(1);
+                    return Err(AudioStreamError::RetryIn(Duration::from_secs(retry_after)));
+                }
+                status if !status.is_success() => {
+                    return Err(AudioStreamError::Fail(
+                        format!("spotify api answered {} for {}", status, url).into(),
+                    ));
+                }
+                _ => {
+                    let body = response.bytes().await.map_err(fail)?;
+                    return serde_json::from_slice(&body).map_err(fail);
+                }
+            }
+        }
+    }
+
+    // hands out the cached access token, fetching a new one once it's about to expire
+    async fn token(&self) -> Result<String, AudioStreamError> {
+        // held while fetching, so concurrent lookups wait for the same token
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if token.expires_at > Instant::now() {
+                return Ok(token.value.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/api/token", self.accounts_url))
+            .basic_auth(
+                self.credentials.client_id.as_ref(),
+                Some(self.credentials.client_secret.as_ref()),
+            )
+            .form(&[("grant_type", "client_credentials")])
+            .send()
+            .await
+            .map_err(fail)?;
+        if !response.status().is_success() {
+            return Err(AudioStreamError::Fail(
+                format!("spotify token request failed with {}", response.status()).into(),
+            ));
+        }
+        let body = response.bytes().await.map_err(fail)?;
+        let token: Token = serde_json::from_slice(&body).map_err(fail)?;
+
+        *cached = Some(CachedToken {
+            value: token.access_token.clone(),
+            expires_at: Instant::now()
+                + Duration::from_secs(token.expires_in).saturating_sub(TOKEN_EXPIRY_MARGIN),
+        });
+
+        Ok(token.access_token)
+    }
+
+    async fn forget_token(&self, rejected: &str) {
+        let mut cached = self.token.lock().await;
+        // another lookup might have replaced it already
+        if cached.as_ref().is_some_and(|token| token.value == rejected) {
+            *cached = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    type Routes = Arc<dyn Fn(&str, &str) -> (u16, String) + Send + Sync>;
+
+    struct MockServer {
+        url: String,
+        // request lines, e.g. "GET /v1/tracks/abc"
+        requests: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl MockServer {
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn api(&self) -> SpotifyApi {
+            let credentials = SpotifyCredential {
+                client_id: Arc::new("id".to_string()),
+                client_secret: Arc::new("secret".to_string()),
+            };
+            SpotifyApi::with_urls(
+                Client::new(),
+                credentials,
+                &self.url,
+                &format!("{}/v1", self.url),
+            )
+        }
+    }
+
+    // serves every request with `routes(server url, request target)`, closing the
+    // connection after each response
+    async fn mock_server(routes: Routes) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let server_url = url.clone();
+        let log = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).into_owned();
+                let line = request.lines().next().unwrap_or_default();
+                let mut parts = line.split(' ');
+                let method = parts.next().unwrap_or_default();
+                let target = parts.next().unwrap_or_default();
+                log.lock().unwrap().push(format!(
+                    "{} {}",
+                    method,
+                    target.split('?').next().unwrap()
+                ));
+
+                let (status, body) = routes(&server_url, target);
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    fn token(name: &str, expires_in: u64) -> (u16, String) {
+        (
+            200,
+            format!(
+                r#"{{"access_token": "{name}", "token_type": "Bearer", "expires_in": {expires_in}}}"#
+            ),
+        )
+    }
+
+    fn track(id: &str, with_album: bool) -> String {
+        let album = if with_album {
+            r#""album": {"id": "al1", "name": "Some Album", "album_type": "album",
+                "artists": [{"id": "ar1", "name": "Some Artist"}],
+                "images": [{"url": "https://i.scdn.co/image/cover"}],
+                "release_date": "2021-05-14", "total_tracks": 12},
+            "external_ids": {"isrc": "USRC12345678"}, "popularity": 42,"#
+        } else {
+            ""
+        };
+        format!(
+            r#"{{"type": "track", "id": "{id}", "name": "Song {id}",
+            "artists": [{{"id": "ar1", "name": "Some Artist"}}, {{"id": "ar2", "name": "Guest"}}],
+            {album}
+            "disc_number": 1, "track_number": 3, "duration_ms": 215000, "explicit": true,
+            "external_urls": {{"spotify": "https://open.spotify.com/track/{id}"}}}}"#
+        )
+    }
+
+    #[test]
+    fn parses_spotify_links() {
+        assert_eq!(
+            Link::parse("https://open.spotify.com/track/abc?si=123"),
+            Some(Link::Track("abc"))
+        );
+        assert_eq!(
+            Link::parse("https://open.spotify.com/intl-de/album/def"),
+            Some(Link::Album("def"))
+        );
+        assert_eq!(
+            Link::parse("spotify:playlist:ghi"),
+            Some(Link::Playlist("ghi"))
+        );
+        assert_eq!(Link::parse("https://open.spotify.com/artist/jkl"), None);
+        assert_eq!(Link::parse("sugar for the pill"), None);
+    }
+
+    #[tokio::test]
+    async fn maps_a_track_and_caches_the_token() {
+        let server = mock_server(Arc::new(|_, target| match target {
+            "/api/token" => token("first", 3600),
+            "/v1/tracks/t1" => (200, track("t1", true)),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+        let api = server.api();
+
+        let song = api
+            .songs("https://open.spotify.com/track/t1")
+            .await
+            .unwrap()
+            .swap_remove(0);
+        api.songs("spotify:track:t1").await.unwrap();
+
+        assert_eq!(song.name, "Song t1");
+        assert_eq!(song.artist, "Some Artist");
+        assert_eq!(song.artists, vec!["Some Artist", "Guest"]);
+        assert_eq!(song.album_name, "Some Album");
+        assert_eq!(song.duration, 215);
+        assert_eq!(song.year, 2021);
+        assert_eq!(song.isrc, "USRC12345678");
+        assert_eq!(song.cover_url, "https://i.scdn.co/image/cover");
+        assert_eq!(song.url, "https://open.spotify.com/track/t1");
+        assert_eq!(song.list_name, None);
+
+        assert_eq!(
+            server.requests(),
+            vec!["POST /api/token", "GET /v1/tracks/t1", "GET /v1/tracks/t1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn refreshes_expired_and_rejected_tokens() {
+        let tokens = Arc::new(AtomicUsize::new(0));
+        let counter = tokens.clone();
+        let server = mock_server(Arc::new(move |_, target| match target {
+            // already within the expiry margin, so never reused
+            "/api/token" => {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                token(&format!("token-{n}"), 30)
+            }
+            "/v1/tracks/t1" => (200, track("t1", true)),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+        let api = server.api();
+
+        api.songs("spotify:track:t1").await.unwrap();
+        api.songs("spotify:track:t1").await.unwrap();
+        assert_eq!(tokens.load(Ordering::SeqCst), 2);
+
+        let rejected = Arc::new(AtomicUsize::new(0));
+        let counter = rejected.clone();
+        let server = mock_server(Arc::new(move |_, target| match target {
+            "/api/token" => token("revoked-or-not", 3600),
+            "/v1/tracks/t1" if counter.fetch_add(1, Ordering::SeqCst) == 0 => {
+                (401, "{}".to_string())
+            }
+            "/v1/tracks/t1" => (200, track("t1", true)),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+
+        server.api().songs("spotify:track:t1").await.unwrap();
+        assert_eq!(
+            server.requests(),
+            vec![
+                "POST /api/token",
+                "GET /v1/tracks/t1",
+                "POST /api/token",
+                "GET /v1/tracks/t1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_album_tracks_across_pages() {
+        let server = mock_server(Arc::new(|url, target| match target {
+            "/api/token" => token("token", 3600),
+            "/v1/albums/al1" => (
+                200,
+                format!(
+                    r#"{{"id": "al1", "name": "Some Album", "album_type": "album",
+                    "artists": [{{"id": "ar1", "name": "Some Artist"}}], "images": [],
+                    "release_date": "2019", "total_tracks": 2, "label": "Some Label",
+                    "external_urls": {{"spotify": "https://open.spotify.com/album/al1"}},
+                    "tracks": {{"items": [{}], "next": "{url}/v1/albums/al1/tracks?offset=1", "total": 2}}}}"#,
+                    track("t1", false)
+                ),
+            ),
+            "/v1/albums/al1/tracks?offset=1" => (
+                200,
+                format!(r#"{{"items": [{}], "next": null, "total": 2}}"#, track("t2", false)),
+            ),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+
+        let songs = server
+            .api()
+            .songs("https://open.spotify.com/album/al1")
+            .await
+            .unwrap();
+
+        assert_eq!(songs.len(), 2);
+        for (i, song) in songs.iter().enumerate() {
+            assert_eq!(song.album_name, "Some Album");
+            assert_eq!(song.publisher, "Some Label");
+            assert_eq!(song.year, 2019);
+            assert_eq!(song.list_name.as_deref(), Some("Some Album"));
+            assert_eq!(
+                song.list_url.as_deref(),
+                Some("https://open.spotify.com/album/al1")
+            );
+            assert_eq!(song.list_position, Some(i as i32 + 1));
+            assert_eq!(song.list_length, Some(2));
+        }
+        assert_eq!(songs[1].name, "Song t2");
+    }
+
+    #[tokio::test]
+    async fn skips_unplayable_playlist_entries() {
+        let server = mock_server(Arc::new(|_, target| match target {
+            "/api/token" => token("token", 3600),
+            "/v1/playlists/p1" => (
+                200,
+                format!(
+                    r#"{{"name": "Mix", "external_urls": {{"spotify": "https://open.spotify.com/playlist/p1"}},
+                    "tracks": {{"items": [{{"track": {}}}, {{"track": null}},
+                        {{"track": {{"type": "episode", "id": "e1", "name": "Podcast"}}}},
+                        {{"track": {}}}], "next": null, "total": 4}}}}"#,
+                    track("t1", true),
+                    track("t2", true)
+                ),
+            ),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+
+        let songs = server.api().songs("spotify:playlist:p1").await.unwrap();
+
+        let names: Vec<_> = songs.iter().map(|song| song.name.as_str()).collect();
+        assert_eq!(names, vec!["Song t1", "Song t2"]);
+        assert_eq!(songs[1].list_name.as_deref(), Some("Mix"));
+        assert_eq!(songs[1].list_position, Some(2));
+        assert_eq!(songs[1].list_length, Some(2));
+    }
+
+    #[tokio::test]
+    async fn stops_paging_at_the_track_cap() {
+        let server = mock_server(Arc::new(|url, target| match target {
+            "/api/token" => token("token", 3600),
+            "/v1/albums/al1" => (
+                200,
+                format!(
+                    r#"{{"id": "al1", "name": "Some Album", "total_tracks": 3,
+                    "tracks": {{"items": [{}], "next": "{url}/v1/albums/al1/tracks?offset=1", "total": 3}}}}"#,
+                    track("t1", false)
+                ),
+            ),
+            "/v1/albums/al1/tracks?offset=1" => (
+                200,
+                format!(
+                    r#"{{"items": [{}], "next": "{url}/v1/albums/al1/tracks?offset=2", "total": 3}}"#,
+                    track("t2", false)
+                ),
+            ),
+            "/v1/playlists/p1" => (
+                200,
+                format!(
+                    r#"{{"name": "Mix", "tracks": {{"items": [{{"track": {}}}, {{"track": null}}],
+                    "next": "{url}/v1/playlists/p1/tracks?offset=2", "total": 6}}}}"#,
+                    track("t1", true)
+                ),
+            ),
+            "/v1/playlists/p1/tracks?offset=2" => (
+                200,
+                format!(
+                    r#"{{"items": [{{"track": {}}}, {{"track": {}}}],
+                    "next": "{url}/v1/playlists/p1/tracks?offset=4", "total": 6}}"#,
+                    track("t2", true),
+                    track("t3", true)
+                ),
+            ),
+            _ => (404, "{}".to_string()),
+        }))
+        .await;
+        let api = server.api().with_max_tracks(2);
+
+        let songs = api.songs("spotify:album:al1").await.unwrap();
+        let names: Vec<_> = songs.iter().map(|song| song.name.as_str()).collect();
+        assert_eq!(names, vec!["Song t1", "Song t2"]);
+        assert_eq!(songs[0].list_length, Some(3));
+
+        let songs = api.songs("spotify:playlist:p1").await.unwrap();
+        let names: Vec<_> = songs.iter().map(|song| song.name.as_str()).collect();
+        assert_eq!(names, vec!["Song t1", "Song t2"]);
+        assert_eq!(songs[0].list_length, Some(6));
+
+        assert_eq!(
+            server.requests(),
+            vec![
+                "POST /api/token",
+                "GET /v1/albums/al1",
+                "GET /v1/albums/al1/tracks",
+                "GET /v1/playlists/p1",
+                "GET /v1/playlists/p1/tracks"
+            ]
+        );
+    }
+}
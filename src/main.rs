@@ -1,11 +1,22 @@
 mod commands;
 mod configs;
+mod events;
 mod input;
 mod models;
+mod player;
 
+use commands::player::autocomplete::SongSuggestions;
 use configs::env::Config;
 use dotenv::dotenv;
+use events::voice_state::IdleDisconnect;
+use input::{
+    audio_cache::AudioCache, cache::ResolveCache, library::Library,
+    sources::spotdl::SpotifyCredential, spotify::SpotifyApi,
+};
+use models::guild::GuildSettingsStore;
+use player::track::PlayerContext;
 use poise::serenity_prelude as serenity;
+use tokio::signal::unix::{signal, SignalKind};
 
 use songbird::typemap::TypeMapKey;
 use songbird::SerenityInit;
@@ -13,7 +24,11 @@ use songbird::SerenityInit;
 // YtDl requests need an HTTP client to operate -- we'll create and store our own.
 use reqwest::Client as HttpClient;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 // Types used by all command functions
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -22,6 +37,17 @@ type Context<'a> = poise::Context<'a, Data, Error>;
 // Custom user data passed to all command functions
 pub struct Data {
     app_config: Config,
+    player: PlayerContext,
+    song_suggestions: Arc<SongSuggestions>,
+    spotify: Arc<SpotifyApi>,
+    // what spotdl and yt-dlp resolved queries to, shared by all guilds
+    resolve_cache: Arc<ResolveCache>,
+    // audio streamed before, when caching it is configured
+    audio_cache: Option<Arc<AudioCache>>,
+    // music directory for /local, when one is configured
+    library: Option<Library>,
+    // pending disconnects of guilds whose voice channel emptied out
+    idle_disconnects: Mutex<HashMap<serenity::GuildId, IdleDisconnect>>,
     // votes: Mutex<HashMap<String, u32>>,
 }
 
@@ -60,6 +86,14 @@ async fn main() {
         Err(err) => panic!("failed to init config {err:?}"),
     };
 
+    // fail right away instead of on the first song someone plays
+    for (tool, variable) in [(env.spotdl(), "SPOTDL_PATH"), (env.ytdlp(), "YTDLP_PATH")] {
+        match tool.locate() {
+            Ok(path) => println!("using {}", path.display()),
+            Err(err) => panic!("failed to init config {err}, set {variable} to point at it"),
+        }
+    }
+
     let options = poise::FrameworkOptions {
         commands: commands::create_command(),
         prefix_options: poise::PrefixFrameworkOptions {
@@ -99,16 +133,8 @@ async fn main() {
         // Enforce command checks even for owners (enforced by default)
         // Set to true to bypass checks, which is useful for testing
         skip_checks_for_owners: false,
-        event_handler: |_ctx, event, _framework, _data| {
-            Box::pin(async move {
-                // todo!: handle events sent by discord
-                // mainly for all users leaving the voice channel, bot should stop all process to save resources
-                println!(
-                    "Got an event in event handler: {:?}",
-                    event.snake_case_name()
-                );
-                Ok(())
-            })
+        event_handler: |ctx, event, _framework, data| {
+            Box::pin(events::handle_event(ctx, event, data))
         },
         ..Default::default()
     };
@@ -116,13 +142,59 @@ async fn main() {
     let intents = serenity::GatewayIntents::non_privileged();
 
     let env_clone = env.clone();
+    let guild_settings = Arc::new(GuildSettingsStore::load(&env.guild_settings_file));
+    let http_client = HttpClient::new();
+    let spotify = Arc::new(
+        SpotifyApi::new(
+            http_client.clone(),
+            SpotifyCredential {
+                client_id: env.spotify_client_id.clone(),
+                client_secret: env.spotify_client_secret.clone(),
+            },
+        )
+        .with_max_tracks(env.max_tracks_per_command),
+    );
+    let resolve_cache = Arc::new(match &env.resolve_cache_file {
+        Some(path) => ResolveCache::load(path),
+        None => ResolveCache::default(),
+    });
+    resolve_cache.spawn_saver();
+    let saved_resolve_cache = resolve_cache.clone();
+    let audio_cache = env.audio_cache_dir.as_ref().map(|dir| {
+        match AudioCache::open(dir, env.audio_cache_max_bytes()) {
+            Ok(audio_cache) => Arc::new(audio_cache),
+            Err(err) => panic!("failed to init config {err}, check AUDIO_CACHE_DIR"),
+        }
+    });
+    let library = env
+        .music_library_dir
+        .as_ref()
+        .map(|dir| match Library::open(dir) {
+            Ok(library) => library,
+            Err(err) => panic!("failed to init config {err}, check MUSIC_LIBRARY_DIR"),
+        });
+    let buffer_next_track = env.buffer_next_track;
     let framework = poise::Framework::builder()
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
                 println!("Logged in as {}", _ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 Ok(Data {
                     app_config: env_clone,
+                    player: PlayerContext {
+                        http: ctx.http.clone(),
+                        songbird: songbird::get(ctx)
+                            .await
+                            .expect("Songbird Voice client placed in at initialisation."),
+                        guild_settings,
+                        buffer_next_track,
+                    },
+                    song_suggestions: Arc::new(SongSuggestions::default()),
+                    spotify,
+                    resolve_cache,
+                    audio_cache,
+                    library,
+                    idle_disconnects: Mutex::new(HashMap::new()),
                     // votes: Mutex::new(HashMap::new()),
                 })
             })
@@ -137,13 +209,27 @@ async fn main() {
         // details ahead of time.
         //
         // Generally, we don't want to make a new Client for every request!
-        .type_map_insert::<HttpKey>(HttpClient::new())
+        .type_map_insert::<HttpKey>(http_client)
         .framework(framework)
         .await;
 
+    let mut client = client.unwrap();
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM can be listened to");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        println!("Shutting down");
+        shard_manager.shutdown_all().await;
+    });
+
     client
-        .unwrap()
         .start()
         .await
-        .expect("The discord bot should run successfully")
+        .expect("The discord bot should run successfully");
+
+    // what changed since the last periodic save would be lost otherwise
+    saved_resolve_cache.flush().await;
 }
@@ -1,22 +1,109 @@
-use std::sync::Arc;
-
-use poise::serenity_prelude::{async_trait, Http};
-use songbird::id::ChannelId;
+use crate::models::guild::LoopMode;
+use crate::player::{
+    embed::now_playing_embed,
+    track::{self, PlayerContext, TrackRequest},
+};
+use poise::serenity_prelude::{async_trait, CreateEmbedAuthor, CreateMessage};
+use songbird::tracks::{PlayMode, TrackQueue};
 use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
 
-struct SongEndNotifier {
-    chan_id: ChannelId,
-    http: Arc<Http>,
+pub struct TrackStartNotifier {
+    pub request: TrackRequest,
+    pub player: PlayerContext,
+    pub queue: TrackQueue,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackStartNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, handle)]) = ctx else {
+            return None;
+        };
+
+        // resuming a paused track fires this event too, only announce the first start
+        if !state.play_time.is_zero() {
+            return None;
+        }
+
+        let settings = self.player.guild_settings.get(self.request.guild_id);
+        if settings.loop_mode == LoopMode::Track {
+            let _ = handle.enable_loop();
+        }
+
+        let queue = self.queue.clone();
+        let player = self.player.clone();
+        tokio::spawn(async move { track::prefetch_next(&queue, &player).await });
+
+        let message = CreateMessage::new().embed(
+            now_playing_embed(&self.request.metadata).author(CreateEmbedAuthor::new("Now playing")),
+        );
+
+        if let Err(e) = self
+            .request
+            .channel_id
+            .send_message(&self.player.http, message)
+            .await
+        {
+            println!("Failed to announce track start: {:?}", e);
+        }
+
+        None
+    }
+}
+
+pub struct SongEndNotifier {
+    pub request: TrackRequest,
+    pub player: PlayerContext,
+    pub queue: TrackQueue,
 }
 
 #[async_trait]
 impl VoiceEventHandler for SongEndNotifier {
-    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
-        // check_msg(
-        // self.chan_id
-        //     .say(&self.http, "Song faded out completely!")
-        //     .await,
-        // );
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, handle)]) = ctx else {
+            return None;
+        };
+
+        // tracks dropped from the queue before they ever played are not worth a message
+        if state.play_time.is_zero() {
+            return None;
+        }
+
+        // only tracks which played to the end go around again, skipped or stopped ones don't
+        let settings = self.player.guild_settings.get(self.request.guild_id);
+        if settings.loop_mode == LoopMode::Queue && state.playing == PlayMode::End {
+            let request = self.request.clone();
+            let player = self.player.clone();
+            // the call may be locked by a command queueing tracks, don't hold up the event
+            // thread waiting on it
+            tokio::spawn(async move {
+                if let Some(handler_lock) = player.songbird.get(request.guild_id) {
+                    let mut handler = handler_lock.lock().await;
+                    track::enqueue(&mut handler, &player, request).await;
+                }
+            });
+            return None;
+        }
+
+        let drained = self
+            .queue
+            .current_queue()
+            .iter()
+            .all(|queued| queued.uuid() == handle.uuid());
+
+        if drained {
+            if let Err(e) = self
+                .request
+                .channel_id
+                .say(
+                    &self.player.http,
+                    "Queue finished, add more songs to keep listening!",
+                )
+                .await
+            {
+                println!("Failed to announce queue end: {:?}", e);
+            }
+        }
 
         None
     }
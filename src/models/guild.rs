@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+pub enum LoopMode {
+    #[default]
+    #[name = "off"]
+    Off,
+    #[name = "track"]
+    Track,
+    #[name = "queue"]
+    Queue,
+}
+
+/// Player settings which apply to a whole guild.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    pub loop_mode: LoopMode,
+    // in percent, 100 being the volume of the source
+    pub volume: u16,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            loop_mode: LoopMode::default(),
+            volume: 100,
+        }
+    }
+}
+
+impl GuildSettings {
+    /// The volume as the scale songbird expects.
+    pub fn volume_scale(&self) -> f32 {
+        f32::from(self.volume) / 100.0
+    }
+}
+
+/// Settings of every guild, written back to `path` on each change so they survive restarts.
+#[derive(Debug, Default)]
+pub struct GuildSettingsStore {
+    path: Option<PathBuf>,
+    settings: RwLock<HashMap<GuildId, GuildSettings>>,
+    // held while writing the file, so an older state never gets written over a newer one
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl GuildSettingsStore {
+    /// Loads the settings saved at `path`, starting over when there are none yet. A file that
+    /// can't be read is moved aside rather than written over.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let settings = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                let mut corrupt_path = OsString::from(&path);
+                corrupt_path.push(".corrupt");
+                let corrupt_path = PathBuf::from(corrupt_path);
+                match std::fs::rename(&path, &corrupt_path) {
+                    Ok(()) => println!(
+                        "Unreadable guild settings {:?} moved to {:?}: {}",
+                        path, corrupt_path, e
+                    ),
+                    Err(rename_e) => println!(
+                        "Unreadable guild settings {:?}: {}, failed to move them aside: {}",
+                        path, e, rename_e
+                    ),
+                }
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self {
+            path: Some(path),
+            settings: RwLock::new(settings),
+            saving: Default::default(),
+        }
+    }
+
+    pub fn get(&self, guild_id: GuildId) -> GuildSettings {
+        self.settings
+            .read()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn update(&self, guild_id: GuildId, update: impl FnOnce(&mut GuildSettings)) {
+        update(self.settings.write().unwrap().entry(guild_id).or_default());
+
+        if let Err(e) = self.save().await {
+            println!("Failed to save guild settings: {}", e);
+        }
+    }
+
+    async fn save(&self) -> std::io::Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+
+        let _saving = self.saving.lock().await;
+        let contents = serde_json::to_vec_pretty(&*self.settings.read().unwrap())?;
+        tokio::task::spawn_blocking(move || write_atomically(&path, &contents)).await?
+    }
+}
+
+// writes next to the file and swaps it in, a crash mid-write must not lose everything
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn settings_survive_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("guild_settings.json");
+        let guild_id = GuildId::new(1);
+
+        let store = GuildSettingsStore::load(&path);
+        store
+            .update(guild_id, |settings| settings.volume = 50)
+            .await;
+        store
+            .update(guild_id, |settings| settings.loop_mode = LoopMode::Queue)
+            .await;
+
+        let settings = GuildSettingsStore::load(&path).get(guild_id);
+        assert_eq!(settings.volume, 50);
+        assert_eq!(settings.loop_mode, LoopMode::Queue);
+    }
+
+    #[tokio::test]
+    async fn unreadable_settings_are_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("guild_settings.json");
+        std::fs::write(&path, "{ not json").unwrap();
+
+        let store = GuildSettingsStore::load(&path);
+        assert_eq!(store.get(GuildId::new(1)).volume, 100);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("guild_settings.json.corrupt")).unwrap(),
+            "{ not json"
+        );
+
+        store
+            .update(GuildId::new(1), |settings| settings.volume = 20)
+            .await;
+        assert_eq!(
+            GuildSettingsStore::load(&path).get(GuildId::new(1)).volume,
+            20
+        );
+    }
+}
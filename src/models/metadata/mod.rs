@@ -1 +1,2 @@
 pub mod spotdl;
+pub mod spotify;
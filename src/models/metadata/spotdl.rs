@@ -1,5 +1,5 @@
-use std::error::Error;
 use serde::{Deserialize, Serialize};
+use std::error::Error;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 
@@ -57,7 +57,7 @@ impl Song {
                 Ok(songs)
             }
             Err(e) => {
-                println!("{}", format!("error: {}", e));
+                println!("error: {}", e);
                 println!("Error reading file");
                 Err(e.into())
             }
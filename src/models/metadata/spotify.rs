@@ -0,0 +1,177 @@
+use crate::models::metadata::spotdl::Song;
+use serde::Deserialize;
+
+// Only the fields of the Spotify Web API objects that end up in a `Song` are modelled here,
+// see https://developer.spotify.com/documentation/web-api/reference
+
+#[derive(Debug, Deserialize)]
+pub struct Token {
+    pub access_token: String,
+    // seconds
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExternalUrls {
+    pub spotify: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExternalIds {
+    pub isrc: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Image {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Artist {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Copyright {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+    pub total: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Album {
+    pub id: String,
+    pub name: String,
+    pub album_type: String,
+    pub artists: Vec<Artist>,
+    pub images: Vec<Image>,
+    pub release_date: String,
+    pub total_tracks: i32,
+    pub external_urls: ExternalUrls,
+    // only part of full album objects
+    pub label: String,
+    pub copyrights: Vec<Copyright>,
+    pub genres: Vec<String>,
+    pub tracks: Page<Track>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Track {
+    // "episode" for podcast episodes in a playlist
+    #[serde(rename = "type")]
+    pub kind: String,
+    // missing for local files of a playlist
+    pub id: Option<String>,
+    pub name: String,
+    pub artists: Vec<Artist>,
+    pub disc_number: i32,
+    pub track_number: i32,
+    pub duration_ms: i32,
+    pub explicit: bool,
+    pub external_urls: ExternalUrls,
+    // the following are missing from the simplified tracks listed in an album
+    pub album: Option<Album>,
+    pub external_ids: ExternalIds,
+    pub popularity: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PlaylistItem {
+    // null once the track got removed from spotify
+    pub track: Option<Track>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Playlist {
+    pub name: String,
+    pub external_urls: ExternalUrls,
+    pub tracks: Page<PlaylistItem>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchResult {
+    pub tracks: Page<Track>,
+}
+
+/// The playlist or album a song was queued as part of.
+pub struct SongList<'a> {
+    pub name: &'a str,
+    pub url: &'a str,
+    pub length: i32,
+}
+
+impl Track {
+    /// Maps the track into the shape spotdl saves songs in, `album` being used for the
+    /// simplified tracks of an album which don't carry one themselves.
+    pub fn into_song(self, album: Option<&Album>, list: Option<(&SongList, i32)>) -> Song {
+        let album = self.album.as_ref().or(album).cloned().unwrap_or_default();
+        let first_artist = self.artists.first().cloned().unwrap_or_default();
+
+        Song {
+            artists: self.artists.iter().map(|a| a.name.clone()).collect(),
+            artist: first_artist.name,
+            genres: album.genres,
+            disc_number: self.disc_number,
+            // spotify doesn't tell, at least as many as this track is on
+            disc_count: self.disc_number.max(1),
+            album_name: album.name,
+            album_artist: album
+                .artists
+                .first()
+                .map(|a| a.name.clone())
+                .unwrap_or_default(),
+            album_type: album.album_type,
+            duration: self.duration_ms / 1000,
+            year: album
+                .release_date
+                .get(..4)
+                .and_then(|year| year.parse().ok())
+                .unwrap_or_default(),
+            date: album.release_date,
+            track_number: self.track_number,
+            tracks_count: album.total_tracks,
+            song_id: self.id.unwrap_or_default(),
+            explicit: self.explicit,
+            publisher: album.label,
+            url: self.external_urls.spotify,
+            isrc: self.external_ids.isrc,
+            cover_url: album
+                .images
+                .first()
+                .map(|i| i.url.clone())
+                .unwrap_or_default(),
+            copyright_text: album
+                .copyrights
+                .first()
+                .map(|c| c.text.clone())
+                .unwrap_or_default(),
+            download_url: None,
+            lyrics: None,
+            popularity: self.popularity,
+            album_id: album.id,
+            list_name: list.map(|(list, _)| list.name.to_string()),
+            list_url: list.map(|(list, _)| list.url.to_string()),
+            list_position: list.map(|(_, position)| position),
+            list_length: list.map(|(list, _)| list.length),
+            name: self.name,
+            artist_id: first_artist.id,
+        }
+    }
+}
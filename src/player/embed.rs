@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::CreateEmbed;
+use songbird::input::AuxMetadata;
+
+/// Formats a duration as `m:ss`, or `h:mm:ss` for anything longer than an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+/// Longest label discord accepts for select menu options and autocomplete choices.
+pub const CHOICE_TEXT_LIMIT: usize = 100;
+
+/// Cuts `text` down to at most `limit` characters, marking it with an ellipsis when it was cut.
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+
+    let mut truncated: String = text.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Renders how far into a track playback is, e.g. `▬▬▬▬🔘▬▬▬▬▬ 1:23 / 3:45`.
+pub fn progress_bar(position: Duration, duration: Option<Duration>) -> String {
+    match duration.filter(|duration| !duration.is_zero()) {
+        Some(duration) => {
+            let ratio = (position.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+            let played = (ratio * PROGRESS_BAR_WIDTH as f64) as usize;
+
+            format!(
+                "{}🔘{} {} / {}",
+                "▬".repeat(played),
+                "▬".repeat(PROGRESS_BAR_WIDTH - played),
+                format_duration(position),
+                format_duration(duration)
+            )
+        }
+        None => format!("{} / live", format_duration(position)),
+    }
+}
+
+pub fn now_playing_embed(meta: &AuxMetadata) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .title(meta.title.clone().unwrap_or("Unknown".to_string()))
+        .description(meta.artist.clone().unwrap_or("Unknown".to_string()))
+        .field(
+            "Album",
+            meta.album.clone().unwrap_or("Unknown".to_string()),
+            true,
+        )
+        .field(
+            "Duration",
+            meta.duration
+                .map(format_duration)
+                .unwrap_or("Unknown".to_string()),
+            true,
+        );
+
+    if let Some(url) = &meta.source_url {
+        embed = embed.url(url);
+    }
+    if let Some(thumbnail) = &meta.thumbnail {
+        embed = embed.thumbnail(thumbnail);
+    }
+
+    embed
+}
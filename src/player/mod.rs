@@ -0,0 +1,3 @@
+pub mod embed;
+pub mod source;
+pub mod track;
@@ -0,0 +1,71 @@
+use crate::input::sources::{
+    attachment::AttachmentFile, local::LocalFile, spotdl::SpotifyDl, ytdl::YoutubeDl,
+};
+use songbird::input::{AudioStreamError, AuxMetadata, Compose, Input};
+
+/// The lazy sources tracks are queued from, kept around so a track can be queued again.
+// only ever held once per queued track, the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum TrackSource {
+    Youtube(YoutubeDl),
+    Spotify(SpotifyDl),
+    Local(LocalFile),
+    Attachment(AttachmentFile),
+}
+
+impl TrackSource {
+    pub async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        match self {
+            TrackSource::Youtube(src) => src.aux_metadata().await,
+            TrackSource::Spotify(src) => src.aux_metadata().await,
+            TrackSource::Local(src) => src.aux_metadata().await,
+            TrackSource::Attachment(src) => src.aux_metadata().await,
+        }
+    }
+
+    pub async fn resolve(&mut self) -> Result<(), AudioStreamError> {
+        match self {
+            TrackSource::Youtube(src) => src.resolve().await,
+            TrackSource::Spotify(src) => src.resolve().await,
+            TrackSource::Local(src) => src.resolve().await,
+            // attachments are streamed straight from discord, nothing to look up
+            TrackSource::Attachment(_) => Ok(()),
+        }
+    }
+}
+
+impl From<YoutubeDl> for TrackSource {
+    fn from(src: YoutubeDl) -> Self {
+        TrackSource::Youtube(src)
+    }
+}
+
+impl From<SpotifyDl> for TrackSource {
+    fn from(src: SpotifyDl) -> Self {
+        TrackSource::Spotify(src)
+    }
+}
+
+impl From<LocalFile> for TrackSource {
+    fn from(src: LocalFile) -> Self {
+        TrackSource::Local(src)
+    }
+}
+
+impl From<AttachmentFile> for TrackSource {
+    fn from(src: AttachmentFile) -> Self {
+        TrackSource::Attachment(src)
+    }
+}
+
+impl From<TrackSource> for Input {
+    fn from(src: TrackSource) -> Self {
+        match src {
+            TrackSource::Youtube(src) => src.into(),
+            TrackSource::Spotify(src) => src.into(),
+            TrackSource::Local(src) => src.into(),
+            TrackSource::Attachment(src) => src.into(),
+        }
+    }
+}
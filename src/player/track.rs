@@ -0,0 +1,306 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::source::TrackSource;
+use crate::models::{
+    events::{SongEndNotifier, TrackStartNotifier},
+    guild::GuildSettingsStore,
+};
+use poise::serenity_prelude::{ChannelId, Context, GuildId, Http, UserId};
+use songbird::{
+    events::{Event, EventData, TrackEvent},
+    input::{AudioStreamError, AuxMetadata},
+    tracks::{Track, TrackHandle, TrackQueue},
+    typemap::TypeMapKey,
+    Call, Songbird,
+};
+
+/// Handles the per-track event handlers need to reach back into the bot.
+#[derive(Clone)]
+pub struct PlayerContext {
+    pub http: Arc<Http>,
+    pub songbird: Arc<Songbird>,
+    pub guild_settings: Arc<GuildSettingsStore>,
+    // whether the next track's stream gets opened right after resolving it, see `prefetch_next`
+    pub buffer_next_track: bool,
+}
+
+/// Details about a queued track, kept in the typemap of every track queued with [`enqueue`].
+#[derive(Clone, Debug)]
+pub struct TrackRequest {
+    pub guild_id: GuildId,
+    pub requester: UserId,
+    // text channel the track was requested from
+    pub channel_id: ChannelId,
+    pub source: TrackSource,
+    // filled in by `resolve_metadata`
+    pub metadata: AuxMetadata,
+}
+
+impl TrackRequest {
+    /// A request for `source` made by whoever invoked the command.
+    pub fn new(ctx: crate::Context<'_>, source: impl Into<TrackSource>) -> Self {
+        Self {
+            guild_id: ctx.guild_id().expect("have guild_id"),
+            requester: ctx.author().id,
+            channel_id: ctx.channel_id(),
+            source: source.into(),
+            metadata: AuxMetadata::default(),
+        }
+    }
+
+    /// Looks up the title, duration and the like of the track. This may run spotdl or yt-dlp,
+    /// so it's done before the call gets locked to [`enqueue`] the track.
+    pub async fn resolve_metadata(mut self) -> Self {
+        // resolving on the kept source caches the metadata there too, for when it's queued again
+        self.metadata = self.source.aux_metadata().await.unwrap_or_default();
+        self
+    }
+}
+
+pub struct TrackRequestKey;
+impl TypeMapKey for TrackRequestKey {
+    type Value = TrackRequest;
+}
+
+/// Queues the requested track, announcing in the requesting channel when it starts playing
+/// and once the queue drained. The request's metadata should be resolved already, see
+/// [`TrackRequest::resolve_metadata`].
+pub async fn enqueue(
+    handler: &mut Call,
+    player: &PlayerContext,
+    request: TrackRequest,
+) -> TrackHandle {
+    // the handlers must be attached before the track reaches the driver, an empty queue
+    // starts playing it right away
+    let settings = player.guild_settings.get(request.guild_id);
+    let mut track = Track::new(request.source.clone().into()).volume(settings.volume_scale());
+    track.events.add_event(
+        EventData::new(
+            Event::Track(TrackEvent::Play),
+            TrackStartNotifier {
+                request: request.clone(),
+                player: player.clone(),
+                queue: handler.queue().clone(),
+            },
+        ),
+        Duration::ZERO,
+    );
+    track.events.add_event(
+        EventData::new(
+            Event::Track(TrackEvent::End),
+            SongEndNotifier {
+                request: request.clone(),
+                player: player.clone(),
+                queue: handler.queue().clone(),
+            },
+        ),
+        Duration::ZERO,
+    );
+
+    let handle = handler.enqueue(track).await;
+    handle
+        .typemap()
+        .write()
+        .await
+        .insert::<TrackRequestKey>(request);
+
+    // a track queued right after the playing one missed its start, when the next track is
+    // usually prefetched
+    if handler.queue().len() == 2 {
+        let queue = handler.queue().clone();
+        let player = player.clone();
+        tokio::spawn(async move { prefetch_next(&queue, &player).await });
+    }
+
+    handle
+}
+
+/// Resolves the track queued after the current one while the current one plays, so the
+/// transition doesn't wait on spotdl or yt-dlp.
+///
+/// Resolving works on a copy of the queued source, which fills the caches it shares with the
+/// real one, failures included. A track that can't be resolved is reported and dropped before
+/// its turn comes.
+pub async fn prefetch_next(queue: &TrackQueue, player: &PlayerContext) {
+    match resolve_next(queue).await {
+        Some(Prefetched::Resolved(next)) if player.buffer_next_track => {
+            let _ = next.make_playable();
+        }
+        Some(Prefetched::Dropped(request, e)) => {
+            let title = request.metadata.title.as_deref().unwrap_or("Unknown");
+            let message = format!("Skipping {}, it can't be played: {}", title, e);
+            if let Err(e) = request.channel_id.say(&player.http, message).await {
+                println!("Failed to report prefetch error: {:?}", e);
+            }
+        }
+        _ => {}
+    }
+}
+
+// short lived and only ever one at a time, the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum Prefetched {
+    Resolved(TrackHandle),
+    // the track failed to resolve and was taken out of the queue
+    Dropped(TrackRequest, AudioStreamError),
+}
+
+async fn resolve_next(queue: &TrackQueue) -> Option<Prefetched> {
+    let next = queue.current_queue().get(1).cloned()?;
+    let request = next
+        .typemap()
+        .read()
+        .await
+        .get::<TrackRequestKey>()
+        .cloned()?;
+    let title = request.metadata.title.as_deref().unwrap_or("Unknown");
+
+    let started = Instant::now();
+    match request.source.clone().resolve().await {
+        Ok(()) => {
+            println!("prefetched {} in {:?}", title, started.elapsed());
+            Some(Prefetched::Resolved(next))
+        }
+        Err(e) => {
+            println!("Prefetching {} failed: {}", title, e);
+            // skipped or removed in the meantime, nothing to report, and once it's playing
+            // the error notifier takes care of it
+            if !queue
+                .current_queue()
+                .iter()
+                .skip(1)
+                .any(|t| t.uuid() == next.uuid())
+            {
+                return None;
+            }
+            queue.modify_queue(|queue| queue.retain(|t| t.uuid() != next.uuid()));
+            let _ = next.stop();
+
+            Some(Prefetched::Dropped(request, e))
+        }
+    }
+}
+
+/// Returns the queue of the call in `guild_id`, if the bot is in one.
+pub async fn guild_queue(ctx: &Context, guild_id: GuildId) -> Option<TrackQueue> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    match manager.get(guild_id) {
+        Some(handler_lock) => Some(handler_lock.lock().await.queue().clone()),
+        None => None,
+    }
+}
+
+/// Checks that `position` points at an upcoming track of a queue holding `len` tracks,
+/// position 0 being the one currently playing.
+pub fn check_position(position: usize, len: usize) -> Result<(), String> {
+    if len <= 1 {
+        return Err("There is nothing queued after the current track".to_string());
+    }
+    if position == 0 || position >= len {
+        return Err(format!("Position must be between 1 and {}", len - 1));
+    }
+
+    Ok(())
+}
+
+pub async fn title(handle: &TrackHandle) -> String {
+    handle
+        .typemap()
+        .read()
+        .await
+        .get::<TrackRequestKey>()
+        .and_then(|request| request.metadata.title.clone())
+        .unwrap_or("Unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::sources::local::LocalFile;
+    use songbird::{Config, Driver};
+    use std::path::Path;
+
+    // a minute of 8khz mono silence, long enough to keep playing throughout a test
+    fn silence(path: &Path) {
+        let data = vec![0u8; 8000 * 2 * 60];
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // pcm, mono, 8khz, 16khz byte rate, 2 byte frames, 16 bits
+        for field in [1u16, 1] {
+            wav.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [8000u32, 16000] {
+            wav.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [2u16, 16] {
+            wav.extend_from_slice(&field.to_le_bytes());
+        }
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        std::fs::write(path, wav).unwrap();
+    }
+
+    async fn queue_file(queue: &TrackQueue, driver: &mut Driver, path: &Path) -> TrackHandle {
+        let source = TrackSource::from(LocalFile::new(path.to_path_buf()));
+        let handle = queue.add(Track::new(source.clone().into()), driver).await;
+        handle
+            .typemap()
+            .write()
+            .await
+            .insert::<TrackRequestKey>(TrackRequest {
+                guild_id: GuildId::new(1),
+                requester: UserId::new(1),
+                channel_id: ChannelId::new(1),
+                source,
+                metadata: AuxMetadata {
+                    title: path.file_name().map(|name| name.to_string_lossy().into()),
+                    ..Default::default()
+                },
+            });
+        handle
+    }
+
+    #[tokio::test]
+    async fn tracks_failing_to_resolve_are_dropped_before_their_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        let playing = dir.path().join("playing.wav");
+        silence(&playing);
+        let next = dir.path().join("next.wav");
+        silence(&next);
+
+        let mut driver = Driver::new(Config::default());
+        let queue = TrackQueue::new();
+        assert!(resolve_next(&queue).await.is_none());
+
+        queue_file(&queue, &mut driver, &playing).await;
+        assert!(resolve_next(&queue).await.is_none());
+
+        let moved = queue_file(&queue, &mut driver, &dir.path().join("moved.wav")).await;
+        let next = queue_file(&queue, &mut driver, &next).await;
+        match resolve_next(&queue).await {
+            Some(Prefetched::Dropped(request, _)) => {
+                assert_eq!(request.metadata.title.as_deref(), Some("moved.wav"))
+            }
+            _ => panic!("the missing file should be dropped"),
+        }
+        let queued: Vec<_> = queue.current_queue().iter().map(|t| t.uuid()).collect();
+        assert_eq!(queued.len(), 2);
+        assert!(!queued.contains(&moved.uuid()));
+
+        match resolve_next(&queue).await {
+            Some(Prefetched::Resolved(handle)) => assert_eq!(handle.uuid(), next.uuid()),
+            _ => panic!("the file after it should resolve"),
+        }
+        assert_eq!(queue.len(), 2);
+    }
+}